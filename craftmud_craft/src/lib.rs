@@ -26,6 +26,30 @@ pub struct InProgress {
 }
 
 impl InProgress {
+    pub fn durability(&self) -> i32 {
+        self.durability
+    }
+
+    pub fn max_durability(&self) -> i32 {
+        self.max_durability
+    }
+
+    pub fn quality(&self) -> i32 {
+        self.quality
+    }
+
+    pub fn max_quality(&self) -> i32 {
+        self.max_quality
+    }
+
+    pub fn progress(&self) -> i32 {
+        self.progress
+    }
+
+    pub fn max_progress(&self) -> i32 {
+        self.max_progress
+    }
+
     fn apply_operation<RNG: rand::Rng>(&mut self, operation: &Operation, rng: &mut RNG) {
         use std::cmp::{min, max};
 
@@ -114,6 +138,14 @@ impl Craft {
         self.state.apply_operation(operation, rng)
     }
 
+    /// The craft's durability, quality, and progress while it is in progress.
+    pub fn in_progress(&self) -> Option<&InProgress> {
+        match self.state {
+            CraftResult::InProgress(ref in_progress) => Some(in_progress),
+            _ => None,
+        }
+    }
+
     pub fn state(&self) -> CraftState {
         match self.state {
             CraftResult::InProgress(_) => CraftState::InProgress,
@@ -0,0 +1,169 @@
+//! Print or replay a TELNET session recorded by the server.
+//!
+//! ```text
+//! replay print <recording>
+//! replay play <recording> <address> [--password <password>]
+//! ```
+//!
+//! `print` shows the recording as a transcript. `play` connects to a server,
+//! such as a freshly started one, and sends the recorded input with the
+//! recorded timing while printing what the server sends back. Passwords are
+//! not recorded, so hidden input is sent as `--password`, or as empty lines
+//! without it.
+//!
+//! See `craftmud_server::telnet::recorder` for the format.
+
+use std::io::{Read as _, Write as _};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use craftmud_server::telnet::recorder::{self, Entry};
+
+const USAGE: &str = "Usage: replay print <recording>\n       replay play <recording> <address> [--password <password>]";
+
+/// How long to keep printing output after the last input is sent.
+const LINGER: Duration = Duration::from_secs(2);
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+    let result = match args[..] {
+        ["print", path] => read(path).map(|entries| print(&entries)),
+        ["play", path, address] => read(path).and_then(|entries| play(&entries, address, "")),
+        ["play", path, address, "--password", password] => read(path).and_then(|entries| play(&entries, address, password)),
+        _ => Err(USAGE.to_string()),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn read(path: &str) -> Result<Vec<Entry>, String> {
+    recorder::read(Path::new(path)).map_err(|err| format!("Unable to read recording {}: {}", path, err))
+}
+
+fn print(entries: &[Entry]) {
+    for entry in entries {
+        let time = format!("{:>9.3}", entry.t() as f64 / 1000.0);
+
+        match entry {
+            Entry::Start { address, unix_ms } => println!("Session from {}, started {} ms after the Unix epoch", address, unix_ms),
+            Entry::Input { text, .. } => println!("{} C: {}", time, text),
+            Entry::HiddenInput { .. } => println!("{} C: (hidden)", time),
+            Entry::Output { text, prompt, .. } => {
+                for line in text.lines() {
+                    println!("{} S: {}", time, line);
+                }
+
+                if *prompt {
+                    println!("{} S: (prompt)", time);
+                }
+            },
+            Entry::Event { event, .. } => println!("{} C: {}", time, event),
+            Entry::Frame { frame, .. } => println!("{} S: {}", time, frame),
+        }
+    }
+}
+
+fn play(entries: &[Entry], address: &str, password: &str) -> Result<(), String> {
+    let mut stream = TcpStream::connect(address).map_err(|err| format!("Unable to connect to {}: {}", address, err))?;
+    let mut reader = stream.try_clone().map_err(|err| err.to_string())?;
+
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        let mut telnet = TelnetStripper::default();
+
+        while let Ok(read) = reader.read(&mut buffer) {
+            if read == 0 {
+                println!("\n(connection closed)");
+                std::process::exit(0);
+            }
+
+            let (text, refusals) = telnet.strip(&buffer[..read]);
+            let _ = reader.write_all(&refusals);
+
+            let stdout = std::io::stdout();
+            let mut stdout = stdout.lock();
+            let _ = stdout.write_all(&text);
+            let _ = stdout.flush();
+        }
+    });
+
+    let started = Instant::now();
+
+    for entry in entries {
+        let line = match entry {
+            Entry::Input { text, .. } => text.as_str(),
+            Entry::HiddenInput { .. } => password,
+            _ => continue,
+        };
+
+        let at = Duration::from_millis(entry.t());
+
+        if let Some(wait) = at.checked_sub(started.elapsed()) {
+            std::thread::sleep(wait);
+        }
+
+        stream.write_all(format!("{}\r\n", line).as_bytes()).map_err(|err| format!("Unable to send input: {}", err))?;
+    }
+
+    std::thread::sleep(LINGER);
+    Ok(())
+}
+
+/// Removes TELNET commands from what the server sends, leaving the text.
+/// Options the server offers are refused with DONT or WONT.
+#[derive(Default)]
+struct TelnetStripper {
+    state: State,
+}
+
+#[derive(Clone, Copy, Default)]
+enum State {
+    #[default]
+    Data,
+    Iac,
+    /// After WILL, WONT, DO, or DONT.
+    Option(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+impl TelnetStripper {
+    /// Returns the text and the refusals to send back.
+    fn strip(&mut self, bytes: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut text = Vec::with_capacity(bytes.len());
+        let mut refusals = vec![];
+
+        for &byte in bytes {
+            self.state = match (self.state, byte) {
+                (State::Data, IAC) => State::Iac,
+                (State::Data, byte) => { text.push(byte); State::Data },
+                (State::Iac, IAC) => { text.push(IAC); State::Data },
+                (State::Iac, SB) => State::Subnegotiation,
+                (State::Iac, verb @ WILL..=DONT) => State::Option(verb),
+                (State::Option(WILL), option) => { refusals.extend_from_slice(&[IAC, DONT, option]); State::Data },
+                (State::Option(DO), option) => { refusals.extend_from_slice(&[IAC, WONT, option]); State::Data },
+                (State::Iac, _) | (State::Option(_), _) => State::Data,
+                (State::Subnegotiation, IAC) => State::SubnegotiationIac,
+                (State::Subnegotiation, _) => State::Subnegotiation,
+                (State::SubnegotiationIac, SE) => State::Data,
+                (State::SubnegotiationIac, _) => State::Subnegotiation,
+            };
+        }
+
+        (text, refusals)
+    }
+}
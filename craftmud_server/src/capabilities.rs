@@ -0,0 +1,105 @@
+//! What the player's client can do.
+//!
+//! Filled in from TTYPE/MTTS and option negotiation so that output can be
+//! adapted to each client instead of guessing.
+
+use serde::{Deserialize, Serialize};
+use telnet_server::{Charset, Mtts, TerminalType};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// The client's name, if it reported one.
+    pub client: Option<String>,
+    pub ansi: bool,
+    pub colors_256: bool,
+    pub truecolor: bool,
+    pub utf8: bool,
+    /// Whether a character set was agreed with CHARSET. If so, it decides
+    /// `utf8` instead of MTTS.
+    #[serde(default)]
+    pub charset_agreed: bool,
+    pub screen_reader: bool,
+    /// Whether MXP was negotiated.
+    pub mxp: bool,
+}
+
+impl Capabilities {
+    /// Update from the terminal types the client reported. Without MTTS, the
+    /// terminal type is used to guess at color support.
+    pub fn update_from_terminal_type(&mut self, terminal_type: TerminalType) {
+        match terminal_type.mtts {
+            Some(mtts) => {
+                self.ansi = mtts.has(Mtts::ANSI);
+                self.colors_256 = mtts.has(Mtts::COLORS_256);
+                self.truecolor = mtts.has(Mtts::TRUECOLOR);
+                if !self.charset_agreed {
+                    self.utf8 = mtts.has(Mtts::UTF8);
+                }
+                self.screen_reader = mtts.has(Mtts::SCREEN_READER);
+            },
+
+            None => {
+                let terminal = terminal_type.terminal.as_deref().unwrap_or("");
+                self.colors_256 = terminal.contains("256COLOR");
+                self.ansi = self.colors_256 || ["ANSI", "XTERM", "VT100"].iter().any(|name| terminal.contains(name));
+            },
+        }
+
+        self.client = terminal_type.client;
+    }
+
+    /// Update from the character set agreed with CHARSET.
+    pub fn update_from_charset(&mut self, charset: Charset) {
+        self.charset_agreed = true;
+        self.utf8 = charset == Charset::Utf8;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capabilities_from_mtts() {
+        let mut capabilities = Capabilities::default();
+
+        capabilities.update_from_terminal_type(TerminalType {
+            client: Some("MUDLET".into()),
+            terminal: Some("ANSI-TRUECOLOR".into()),
+            mtts: Some(Mtts(Mtts::ANSI | Mtts::UTF8 | Mtts::TRUECOLOR)),
+        });
+
+        assert!(capabilities.ansi && capabilities.truecolor && capabilities.utf8);
+        assert!(!capabilities.colors_256 && !capabilities.screen_reader);
+    }
+
+    #[test]
+    fn capabilities_from_terminal_name() {
+        let mut capabilities = Capabilities::default();
+
+        capabilities.update_from_terminal_type(TerminalType {
+            client: Some("XTERM-256COLOR".into()),
+            terminal: Some("XTERM-256COLOR".into()),
+            mtts: None,
+        });
+
+        assert!(capabilities.ansi && capabilities.colors_256);
+        assert!(!capabilities.utf8);
+    }
+
+    #[test]
+    fn agreed_charset_wins_over_mtts() {
+        let mut capabilities = Capabilities::default();
+        let mtts = |flags| TerminalType { client: None, terminal: None, mtts: Some(Mtts(flags)) };
+
+        capabilities.update_from_terminal_type(mtts(Mtts::UTF8));
+        assert!(capabilities.utf8);
+
+        capabilities.update_from_charset(Charset::Latin1);
+        assert!(!capabilities.utf8);
+
+        // MTTS is reported again while cycling terminal types.
+        capabilities.update_from_terminal_type(mtts(Mtts::UTF8));
+        assert!(!capabilities.utf8);
+    }
+}
@@ -0,0 +1,265 @@
+//! Server configuration.
+//!
+//! Read from the file named by `CRAFTMUD_CONFIG`, or `craftmud.toml` in the
+//! working directory. The file is optional and every setting has a default.
+//! See `craftmud.example.toml`.
+
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The name of the MUD, as reported to MUD listing sites.
+    pub name: String,
+
+    /// Addresses to accept connections on. Each `[[listen]]` table in the
+    /// file is one listener.
+    pub listen: Vec<Listener>,
+
+    /// Load balancers whose connections start with a PROXY protocol header
+    /// giving the client's address. Applies to TELNET listeners.
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// Limits on clients, over TELNET and WebSockets alike.
+    pub limits: Limits,
+
+    /// Limits on output waiting to be sent to each client.
+    pub output_queue: OutputQueue,
+
+    /// How long players may be idle.
+    pub idle: IdleTimeouts,
+
+    /// Which TELNET sessions are recorded.
+    pub record: Recording,
+
+    /// Addresses whose players may use admin commands such as `copyover`.
+    pub admins: Vec<IpAddr>,
+}
+
+/// An address to accept connections on and the protocol spoken there.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "protocol", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Listener {
+    /// TELNET without encryption.
+    Plain { address: String },
+
+    /// TELNET over TLS.
+    Tls {
+        address: String,
+
+        /// PEM file with the certificate chain.
+        certificate: PathBuf,
+
+        /// PEM file with the private key.
+        key: PathBuf,
+    },
+
+    /// WebSockets for browser clients.
+    Websocket { address: String },
+}
+
+impl Listener {
+    pub fn address(&self) -> &str {
+        match self {
+            Listener::Plain { address } | Listener::Tls { address, .. } | Listener::Websocket { address } => address,
+        }
+    }
+
+    /// The protocol, for log messages.
+    pub fn protocol(&self) -> &'static str {
+        match self {
+            Listener::Plain { .. } => "TELNET",
+            Listener::Tls { .. } => "TELNET over TLS",
+            Listener::Websocket { .. } => "WebSockets",
+        }
+    }
+}
+
+/// Clients that break one of these are told why and disconnected.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Connections open at once from one IP address.
+    pub connections_per_ip: usize,
+
+    /// Bytes in one line of input.
+    pub line_length: usize,
+
+    /// Lines of input from one connection per second.
+    pub lines_per_second: u32,
+
+    /// Connections accepted per second, from all addresses together.
+    pub accepts_per_second: u32,
+}
+
+/// Counted in frames, where each frame is one batch of output such as a
+/// paragraph and its prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputQueue {
+    /// Frames that may wait before the client is disconnected for not
+    /// keeping up.
+    pub capacity: usize,
+
+    /// Frames that may wait before output that is not essential, such as
+    /// GMCP and MSDP, is dropped.
+    pub high_water_mark: usize,
+}
+
+/// In seconds. A timeout of 0 means players are never disconnected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdleTimeouts {
+    /// While logging in or choosing a character.
+    pub login: u64,
+
+    /// While in the tutorial.
+    pub tutorial: u64,
+
+    /// While playing.
+    pub playing: u64,
+
+    /// How long before being disconnected players are warned.
+    pub warning: u64,
+
+    /// How often to send TELNET NOP, or a WebSocket ping, so that dead
+    /// connections are noticed. 0 sends none.
+    pub keepalive: u64,
+}
+
+/// Sessions are only recorded when a directory is given.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Recording {
+    /// Where recordings are written, one file per connection.
+    pub directory: Option<PathBuf>,
+
+    /// Only record connections from these addresses. Empty records every
+    /// connection.
+    pub addresses: Vec<IpAddr>,
+}
+
+impl Recording {
+    /// The directory to record the connection from `ip` in, if it is
+    /// recorded.
+    pub fn directory_for(&self, ip: IpAddr) -> Option<&Path> {
+        if self.addresses.is_empty() || self.addresses.contains(&ip) {
+            self.directory.as_deref()
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            name: "CraftMud".to_string(),
+            listen: vec![Listener::Plain { address: "127.0.0.1:5431".to_string() }],
+            trusted_proxies: vec![],
+            limits: Limits::default(),
+            output_queue: OutputQueue::default(),
+            idle: IdleTimeouts::default(),
+            record: Recording::default(),
+            admins: vec![],
+        }
+    }
+}
+
+impl Default for IdleTimeouts {
+    fn default() -> Self {
+        Self {
+            login: 300,
+            tutorial: 1800,
+            playing: 3600,
+            warning: 60,
+            keepalive: 60,
+        }
+    }
+}
+
+impl Default for OutputQueue {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            high_water_mark: 256,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        telnet_server::Limits::default().into()
+    }
+}
+
+impl From<telnet_server::Limits> for Limits {
+    fn from(limits: telnet_server::Limits) -> Self {
+        let telnet_server::Limits { connections_per_ip, line_length, lines_per_second, accepts_per_second } = limits;
+        Self { connections_per_ip, line_length, lines_per_second, accepts_per_second }
+    }
+}
+
+impl From<Limits> for telnet_server::Limits {
+    fn from(limits: Limits) -> Self {
+        let Limits { connections_per_ip, line_length, lines_per_second, accepts_per_second } = limits;
+        Self { connections_per_ip, line_length, lines_per_second, accepts_per_second }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self, String> {
+        let (path, required) = match std::env::var_os("CRAFTMUD_CONFIG") {
+            Some(path) => (PathBuf::from(path), true),
+            None => (PathBuf::from("craftmud.toml"), false),
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).map_err(|err| format!("Invalid config file {}: {}", path.display(), err)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(err) => Err(format!("Unable to read config file {}: {}", path.display(), err)),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_listeners() {
+        let config = Config::parse(r#"
+            [[listen]]
+            protocol = "plain"
+            address = "0.0.0.0:5431"
+
+            [[listen]]
+            protocol = "tls"
+            address = "0.0.0.0:5432"
+            certificate = "cert.pem"
+            key = "key.pem"
+
+            [[listen]]
+            protocol = "websocket"
+            address = "127.0.0.1:5433"
+        "#).unwrap();
+
+        assert_eq!(config.listen, vec![
+            Listener::Plain { address: "0.0.0.0:5431".into() },
+            Listener::Tls { address: "0.0.0.0:5432".into(), certificate: "cert.pem".into(), key: "key.pem".into() },
+            Listener::Websocket { address: "127.0.0.1:5433".into() },
+        ]);
+
+        assert_eq!(Config::parse("trusted_proxies = [\"10.0.0.2\"]\n").unwrap().trusted_proxies, vec![IpAddr::from([10, 0, 0, 2])]);
+        assert_eq!(Config::parse("[limits]\nlines_per_second = 5\n").unwrap().limits.lines_per_second, 5);
+        assert!(Config::parse("[[listen]]\nprotocol = \"tls\"\naddress = \"0.0.0.0:5432\"\n").is_err());
+        assert_eq!(Config::parse("").unwrap().listen, Config::default().listen);
+    }
+}
@@ -0,0 +1,224 @@
+//! Hot reboot, or copyover: replacing the running server with a new build of
+//! it without disconnecting players.
+//!
+//! Sending the server SIGUSR1, as with `kill -USR1 <pid>`, starts a copyover.
+//! So does the `copyover` command, for players connecting from one of the
+//! `admins` addresses in the config. There are no admin accounts yet.
+//!
+//! The game saves what each player is doing, the TELNET connections are
+//! detached, and the server execs its binary again with `CRAFTMUD_COPYOVER`
+//! naming a file holding both. The new server resumes the connections and
+//! puts the players back where they were, so they only see a short pause. If
+//! the exec fails, this server resumes them itself instead.
+//!
+//! Connections over TLS and WebSockets can't be handed over and are closed
+//! by the exec. They are kept open until then, so that players on them keep
+//! playing if it fails. Players logging in are asked for their password again, and players who
+//! were registering start over.
+//!
+//! Handing sockets to the new server needs unix. Elsewhere, copyover never
+//! starts and the server is started fresh each time.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+use crossbeam_channel::Receiver;
+use legion::prelude::*;
+use serde::{Deserialize, Serialize};
+use telnet_server::StreamState;
+use tokio::sync::mpsc;
+
+use crate::capabilities::Capabilities;
+use crate::login::{AccountName, LoginMachine};
+use crate::place::PlaceId;
+use crate::play_state::PlayState;
+use crate::tutorial::Tutorial;
+use crate::window_size::WindowSize;
+
+/// Told to players whose connection is handed over.
+pub const RESTARTING: &str = "\r\nThe server is restarting. Please wait...\r\n";
+
+/// Told to players whose connection can't be handed over.
+pub const RESTARTING_WITHOUT_HANDOVER: &str = "\r\nThe server is restarting. Connections over TLS and WebSockets can't be kept, so if it does, you will need to reconnect.\r\n";
+
+/// Asks for a copyover from in game.
+#[derive(Debug)]
+pub struct Start;
+
+/// Asks the game to save its players.
+#[derive(Debug)]
+pub struct Requested;
+
+/// The game's side of a copyover.
+pub struct Copyover {
+    pub recv_request: Receiver<Requested>,
+    pub send_saved: mpsc::UnboundedSender<SavedPlayers>,
+    pub send_start: mpsc::UnboundedSender<Start>,
+    /// Addresses that may use the `copyover` command.
+    pub admins: Vec<IpAddr>,
+}
+
+impl Copyover {
+    /// Whether the player at `addr` may use the `copyover` command.
+    pub fn allows(&self, addr: SocketAddr) -> bool {
+        self.admins.contains(&addr.ip())
+    }
+
+    /// Start a copyover. Returns what to tell whoever started it.
+    pub fn start(&self) -> &'static str {
+        if !cfg!(unix) {
+            return "Copyover needs unix, so this server can't do it.";
+        }
+
+        let _ignore_lack_of_recv = self.send_start.send(Start);
+        "Starting copyover."
+    }
+}
+
+/// Players by the address of their connection.
+pub type SavedPlayers = HashMap<SocketAddr, SavedPlayer>;
+
+/// What the new server needs to put a player back where they were.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub play_state: SavedPlayState,
+    pub window_size: WindowSize,
+    pub capabilities: Capabilities,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "play_state", rename_all = "snake_case")]
+pub enum SavedPlayState {
+    Login { account_name: Option<String> },
+    Tutorial { place: PlaceId, step: usize },
+}
+
+impl SavedPlayState {
+    pub(crate) fn play_state(&self) -> PlayState {
+        match self {
+            SavedPlayState::Login { .. } => PlayState::Login,
+            SavedPlayState::Tutorial { .. } => PlayState::Tutorial,
+        }
+    }
+}
+
+/// Asks every TELNET connection to detach its stream and send it back, or
+/// `None` if it can't be detached.
+#[derive(Clone)]
+pub struct Detach(pub mpsc::UnboundedSender<Option<Detached>>);
+
+pub struct Detached {
+    pub addr: SocketAddr,
+    pub tcp: std::net::TcpStream,
+    pub state: StreamState,
+}
+
+/// A connection handed over by the old server, or kept by this one when the
+/// new server couldn't be started.
+pub struct Resumed {
+    pub addr: SocketAddr,
+    pub tcp: std::net::TcpStream,
+    pub state: StreamState,
+    /// Missing if the connection was too new to have been added to the game.
+    pub player: Option<SavedPlayer>,
+}
+
+/// System that saves every player when a copyover is requested.
+pub fn save_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("copyover")
+    .read_resource::<Copyover>()
+    .with_query(<(Read<SocketAddr>, Read<WindowSize>, Read<Capabilities>, TryRead<LoginMachine>, TryRead<Tutorial>)>::query())
+    .build(|_commands, world, copyover, query| {
+        if copyover.recv_request.try_recv().is_err() {
+            return;
+        }
+
+        let mut players = SavedPlayers::new();
+
+        for (addr, window_size, capabilities, login, tutorial) in query.iter(world) {
+            let play_state = match (login, tutorial) {
+                (Some(login), _) => SavedPlayState::Login { account_name: login.account_name().map(|name| name.0.clone()) },
+                (None, Some(tutorial)) => SavedPlayState::Tutorial { place: tutorial.place(), step: tutorial.step() },
+                (None, None) => continue,
+            };
+
+            players.insert(*addr, SavedPlayer { play_state, window_size: *window_size, capabilities: (*capabilities).clone() });
+        }
+
+        println!("Saved {} players for copyover", players.len());
+        let _ignore_lack_of_recv = copyover.send_saved.send(players);
+    })
+}
+
+#[cfg(unix)]
+mod unix;
+
+#[cfg(unix)]
+pub use unix::{copyover_on_request, take_resumed};
+
+/// Copyover isn't supported here, so this does nothing.
+#[cfg(not(unix))]
+pub async fn copyover_on_request(_recv_start: mpsc::UnboundedReceiver<Start>, _send_request: crossbeam_channel::Sender<Requested>, _recv_saved: mpsc::UnboundedReceiver<SavedPlayers>, _detach: tokio::sync::broadcast::Sender<Detach>, _send_resumed: mpsc::UnboundedSender<Resumed>) -> Result<(), tokio::io::Error> {
+    Ok(())
+}
+
+/// Copyover isn't supported here, so nothing is ever handed over.
+#[cfg(not(unix))]
+pub fn take_resumed() -> Vec<Resumed> {
+    vec![]
+}
+
+impl SavedPlayer {
+    /// The login machine to resume with, if the player was logging in.
+    pub(crate) fn login(&self) -> Option<LoginMachine> {
+        match self.play_state {
+            SavedPlayState::Login { ref account_name } => Some(LoginMachine::resume(account_name.clone().map(AccountName))),
+            SavedPlayState::Tutorial { .. } => None,
+        }
+    }
+
+    /// The tutorial to resume, if the player was in it.
+    pub fn tutorial(&self) -> Option<Tutorial> {
+        match self.play_state {
+            SavedPlayState::Tutorial { place, step } => Some(Tutorial::resume(place, step)),
+            SavedPlayState::Login { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn saved_players_round_trip() {
+        let mut realm = crate::place::Realm::new();
+        let player = SavedPlayer {
+            play_state: SavedPlayState::Tutorial { place: realm.next_id(), step: 1 },
+            window_size: WindowSize { width: 120, height: 40 },
+            capabilities: Capabilities { client: Some("MUDLET".into()), mxp: true, ..Capabilities::default() },
+        };
+
+        let json = serde_json::to_string(&player).unwrap();
+        assert_eq!(serde_json::from_str::<SavedPlayer>(&json).unwrap(), player);
+        assert_eq!(player.play_state.play_state(), PlayState::Tutorial);
+        assert!(player.login().is_none());
+    }
+
+    #[test]
+    fn only_admins_may_start_copyover() {
+        let (send_start, mut recv_start) = mpsc::unbounded_channel();
+        let copyover = Copyover {
+            recv_request: crossbeam_channel::never(),
+            send_saved: mpsc::unbounded_channel().0,
+            send_start,
+            admins: vec!["127.0.0.1".parse().unwrap()],
+        };
+
+        assert!(copyover.allows("127.0.0.1:4000".parse().unwrap()));
+        assert!(!copyover.allows("10.0.0.1:4000".parse().unwrap()));
+
+        copyover.start();
+        assert_eq!(recv_start.try_recv().is_ok(), cfg!(unix));
+    }
+}
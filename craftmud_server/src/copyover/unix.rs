@@ -0,0 +1,209 @@
+//! The parts of copyover that need unix: the signal starting it, and
+//! handing sockets to the new server across exec.
+
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::net::SocketAddr;
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::os::unix::io::{AsRawFd as _, FromRawFd as _, RawFd};
+use std::os::unix::process::CommandExt as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use telnet_server::{Charset, StreamState, TelnetOption};
+use tokio::io::Error as TokioIoError;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{broadcast, mpsc};
+
+use super::{Detach, Detached, Requested, Resumed, SavedPlayer, SavedPlayers, Start};
+
+/// Names the file the old server saved its state to.
+const STATE_VAR: &str = "CRAFTMUD_COPYOVER";
+
+/// How long to wait for connections to send their remaining output and
+/// detach.
+const DETACH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connection as written to the state file.
+#[derive(Serialize, Deserialize)]
+struct SavedConnection {
+    fd: RawFd,
+    addr: SocketAddr,
+    local_options: Vec<u8>,
+    remote_options: Vec<u8>,
+    charset: String,
+    msdp_reported: Vec<String>,
+    #[serde(default)]
+    input: Vec<u8>,
+    player: Option<SavedPlayer>,
+}
+
+/// Copyover each time the server gets SIGUSR1 or a `Start` from in game.
+///
+/// If the new server can't be started, the detached connections are sent to
+/// `send_resumed` and this server keeps running with them, just as the new
+/// server would have.
+pub async fn copyover_on_request(mut recv_start: mpsc::UnboundedReceiver<Start>, send_request: Sender<Requested>, mut recv_saved: mpsc::UnboundedReceiver<SavedPlayers>, detach: broadcast::Sender<Detach>, send_resumed: mpsc::UnboundedSender<Resumed>) -> Result<(), TokioIoError> {
+    let mut signals = signal(SignalKind::user_defined1())?;
+
+    loop {
+        tokio::select! {
+            Some(()) = signals.recv() => println!("Starting copyover for SIGUSR1"),
+            Some(Start) = recv_start.recv() => println!("Starting copyover from in game"),
+            else => break,
+        }
+
+        let _ignore_lack_of_recv = send_request.send(Requested);
+
+        let mut players = match recv_saved.recv().await {
+            Some(players) => players,
+            None => break,
+        };
+
+        let connections = detach_connections(&detach).await.into_iter()
+        .map(|Detached { addr, tcp, state }| Resumed { addr, tcp, state, player: players.remove(&addr) })
+        .collect::<Vec<_>>();
+
+        let err = exec(&connections);
+        eprintln!("Copyover failed: {}. Resuming {} connections in this server.", err, connections.len());
+
+        for connection in connections {
+            let _ignore_lack_of_recv = send_resumed.send(connection);
+        }
+    }
+
+    Ok(())
+}
+
+async fn detach_connections(detach: &broadcast::Sender<Detach>) -> Vec<Detached> {
+    let (send_detached, mut recv_detached) = mpsc::unbounded_channel();
+
+    // Every connection task is subscribed, so this is how many will answer.
+    let connections = detach.send(Detach(send_detached)).unwrap_or(0);
+    let mut detached = Vec::with_capacity(connections);
+
+    let receiving = async {
+        for _ in 0..connections {
+            match recv_detached.recv().await {
+                Some(Some(connection)) => detached.push(connection),
+                Some(None) => {},
+                None => break,
+            }
+        }
+    };
+
+    if tokio::time::timeout(DETACH_TIMEOUT, receiving).await.is_err() {
+        eprintln!("Copyover is continuing without connections that did not detach in time");
+    }
+
+    detached
+}
+
+/// Start the server again with the connections, returning why it couldn't.
+fn exec(connections: &[Resumed]) -> std::io::Error {
+    let path = std::env::temp_dir().join(format!("craftmud-copyover-{}.json", std::process::id()));
+
+    let connections = connections.iter().map(|Resumed { addr, tcp, state, player }| SavedConnection {
+        fd: tcp.as_raw_fd(),
+        addr: *addr,
+        local_options: state.local.iter().map(|&option| option.into()).collect(),
+        remote_options: state.remote.iter().map(|&option| option.into()).collect(),
+        charset: state.charset.name().to_string(),
+        msdp_reported: state.msdp_reported.clone(),
+        input: state.input.clone(),
+        player: player.clone(),
+    }).collect::<Vec<_>>();
+
+    if let Err(err) = write_state(&path, &connections) {
+        return err;
+    }
+
+    println!("Copying over with {} connections", connections.len());
+
+    // The binary is found again by the name it was started with, since the
+    // path of the running binary is gone once a new build replaces it.
+    let mut args = std::env::args_os();
+    let program = args.next().unwrap_or_else(|| "server".into());
+
+    let err = Command::new(program).args(args).env(STATE_VAR, &path).exec();
+    let _ = std::fs::remove_file(&path);
+    err
+}
+
+/// Write the state file. It must not exist yet, so that nobody else sharing
+/// the temporary directory can have put a file or symlink there first. Only
+/// this user can read it.
+fn write_state(path: &Path, connections: &[SavedConnection]) -> std::io::Result<()> {
+    let json = serde_json::to_vec(connections)?;
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+    file.write_all(&json)
+}
+
+/// Take the connections handed over by the old server, if this server was
+/// started by a copyover.
+pub fn take_resumed() -> Vec<Resumed> {
+    let path = match std::env::var_os(STATE_VAR) {
+        Some(path) => PathBuf::from(path),
+        None => return vec![],
+    };
+
+    std::env::remove_var(STATE_VAR);
+
+    let connections = std::fs::read(&path)
+    .map_err(|err| err.to_string())
+    .and_then(|json| serde_json::from_slice::<Vec<SavedConnection>>(&json).map_err(|err| err.to_string()));
+
+    let _ = std::fs::remove_file(&path);
+
+    let connections = match connections {
+        Ok(connections) => connections,
+        Err(err) => {
+            eprintln!("Unable to read copyover state {}: {}", path.display(), err);
+            return vec![];
+        },
+    };
+
+    println!("Resuming {} connections after copyover", connections.len());
+
+    connections.into_iter().map(|connection| Resumed {
+        addr: connection.addr,
+        // Safe since the old server handed this socket to us.
+        tcp: unsafe { std::net::TcpStream::from_raw_fd(connection.fd) },
+        state: StreamState {
+            local: connection.local_options.into_iter().map(TelnetOption::from).collect(),
+            remote: connection.remote_options.into_iter().map(TelnetOption::from).collect(),
+            charset: Charset::from_name(&connection.charset).unwrap_or_default(),
+            msdp_reported: connection.msdp_reported,
+            input: connection.input,
+        },
+        player: connection.player,
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    use super::*;
+
+    #[test]
+    fn state_files_are_private_and_new() {
+        let dir = std::env::temp_dir().join(format!("craftmud_copyover_state_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("state.json");
+        write_state(&path, &[]).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        // Someone else's link is not followed.
+        let link = dir.join("link.json");
+        std::os::unix::fs::symlink(dir.join("target.json"), &link).unwrap();
+        assert!(write_state(&link, &[]).is_err());
+        assert!(!dir.join("target.json").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+//! Crafts that players are working on.
+//!
+//! A player crafting has a `Craft` component. Its progress is published to
+//! MSDP clients and as GMCP `Char.Vitals` each time it changes.
+
+use craftmud_craft::{Craft, CraftState};
+use legion::prelude::*;
+use serde_json::{json, Value};
+use telnet_server::MsdpValue;
+
+use crate::gmcp::{Gmcp, OptionGmcpExt};
+use crate::msdp::{Msdp, OptionMsdpExt};
+
+/// The MSDP variables describing a craft. Progress is only included while
+/// the craft is in progress.
+pub fn progress_variables(craft: &Craft) -> Vec<(&'static str, MsdpValue)> {
+    let mut variables = vec![("CRAFT_STATE", state_name(craft.state()).into())];
+
+    if let Some(in_progress) = craft.in_progress() {
+        variables.extend(vec![
+            ("CRAFT_PROGRESS", in_progress.progress().to_string().into()),
+            ("CRAFT_MAX_PROGRESS", in_progress.max_progress().to_string().into()),
+            ("CRAFT_QUALITY", in_progress.quality().to_string().into()),
+            ("CRAFT_MAX_QUALITY", in_progress.max_quality().to_string().into()),
+            ("CRAFT_DURABILITY", in_progress.durability().to_string().into()),
+            ("CRAFT_MAX_DURABILITY", in_progress.max_durability().to_string().into()),
+        ]);
+    }
+
+    variables
+}
+
+/// The data of a GMCP `Char.Vitals` message. `craft` is null while the player
+/// isn't crafting.
+pub fn vitals(craft: Option<&Craft>) -> Value {
+    let craft = craft.map(|craft| {
+        let mut vitals = json!({ "state": state_name(craft.state()) });
+
+        if let Some(in_progress) = craft.in_progress() {
+            vitals["progress"] = in_progress.progress().into();
+            vitals["max_progress"] = in_progress.max_progress().into();
+            vitals["quality"] = in_progress.quality().into();
+            vitals["max_quality"] = in_progress.max_quality().into();
+            vitals["durability"] = in_progress.durability().into();
+            vitals["max_durability"] = in_progress.max_durability().into();
+        }
+
+        vitals
+    });
+
+    json!({ "craft": craft })
+}
+
+fn state_name(state: CraftState) -> &'static str {
+    match state {
+        CraftState::InProgress => "in progress",
+        CraftState::LowQuality => "low quality",
+        CraftState::HighQuality => "high quality",
+        CraftState::Failure => "failure",
+    }
+}
+
+/// System that publishes the progress of crafts that changed.
+pub fn progress_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("craft_progress")
+    .with_query(<(Read<Craft>, Write<Option<Msdp>>, Write<Option<Gmcp>>)>::query().filter(changed::<Craft>()))
+    .build(|_commands, world, _resources, query| {
+        for (craft, mut msdp, mut gmcp) in query.iter_mut(world) {
+            for (variable, value) in progress_variables(&craft) {
+                msdp.set_msdp(variable, value);
+            }
+
+            gmcp.push_gmcp("Char.Vitals", vitals(Some(&craft)));
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changed_crafts_are_published() {
+        let mut world = World::new();
+        let mut resources = Resources::default();
+        let mut schedule = Schedule::builder().add_system(progress_system()).build();
+
+        world.insert((), vec![(Craft::new(0, 10, 2, 20, 5), None::<Msdp>, None::<Gmcp>)]);
+        schedule.execute(&mut world, &mut resources);
+
+        let published = <Write<Option<Msdp>>>::query().iter_mut(&mut world).next().unwrap().take().is_some();
+        assert!(published);
+
+        // Nothing is published again until the craft changes.
+        schedule.execute(&mut world, &mut resources);
+        assert!(<Read<Option<Msdp>>>::query().iter(&world).next().unwrap().is_none());
+
+        let variables = progress_variables(&Craft::new(0, 10, 2, 20, 5));
+        assert!(variables.contains(&("CRAFT_STATE", "in progress".into())));
+        assert!(variables.contains(&("CRAFT_PROGRESS", "2".into())));
+        assert!(variables.contains(&("CRAFT_MAX_DURABILITY", "5".into())));
+
+        assert_eq!(vitals(None), json!({ "craft": null }));
+        assert_eq!(vitals(Some(&Craft::new(0, 10, 2, 20, 5)))["craft"]["progress"], json!(2));
+    }
+}
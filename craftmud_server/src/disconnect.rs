@@ -0,0 +1,88 @@
+//! Reacting to players' connections closing.
+
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use legion::prelude::*;
+
+use crate::output_queue::OutputSender;
+use crate::play_state::PlayState;
+use crate::telnet::{DisconnectReceiver, EventReceiver, InputReceiver};
+
+/// A playing character whose connection closed. The character stays in the
+/// world so that the player can reconnect to it.
+#[derive(Debug, Clone, Copy)]
+pub struct LinkDead {
+    pub since: Instant,
+}
+
+/// System that handles closed connections.
+///
+/// Entities still logging in or in the tutorial are removed. Playing
+/// characters lose their connection components and are marked `LinkDead`.
+pub fn disconnect_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("disconnect")
+    .with_query(<(Read<DisconnectReceiver>, Read<SocketAddr>, Read<PlayState>)>::query())
+    .build(|commands, world, _resources, query| {
+        for (entity, (recv_disconnect, addr, play_state)) in query.iter_entities(world) {
+            // The sender being dropped without sending also means the
+            // connection is gone.
+            if let Err(crossbeam_channel::TryRecvError::Empty) = recv_disconnect.try_recv() {
+                continue;
+            }
+
+            match *play_state {
+                PlayState::Playing => {
+                    println!("{} disconnected. Their character is now link-dead.", *addr);
+
+                    commands.remove_component::<InputReceiver>(entity);
+                    commands.remove_component::<EventReceiver>(entity);
+                    commands.remove_component::<OutputSender>(entity);
+                    commands.remove_component::<DisconnectReceiver>(entity);
+                    commands.add_component(entity, LinkDead { since: Instant::now() });
+                },
+
+                PlayState::Login | PlayState::ChooseCharacter | PlayState::Tutorial | PlayState::Quitting => {
+                    println!("{} disconnected.", *addr);
+
+                    commands.delete(entity);
+                },
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crossbeam_channel as channel;
+
+    use crate::telnet::Disconnected;
+
+    #[test]
+    fn closed_connections() {
+        let mut world = World::new();
+        let mut resources = Resources::default();
+        let mut schedule = Schedule::builder().add_system(disconnect_system()).flush().build();
+        let addr: SocketAddr = ([127, 0, 0, 1], 5431).into();
+
+        let (send_tutorial_disconnect, recv_tutorial_disconnect) = channel::bounded::<Disconnected>(1);
+        let (send_playing_disconnect, recv_playing_disconnect) = channel::bounded::<Disconnected>(1);
+        let (_send_input, recv_input) = channel::unbounded::<String>();
+
+        world.insert((), vec![(recv_tutorial_disconnect, addr, PlayState::Tutorial)]);
+        world.insert((), vec![(recv_playing_disconnect, addr, PlayState::Playing, recv_input)]);
+
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(<Read<PlayState>>::query().iter(&world).count(), 2);
+
+        send_tutorial_disconnect.send(Disconnected).unwrap();
+        drop(send_playing_disconnect);
+        schedule.execute(&mut world, &mut resources);
+
+        assert_eq!(<Read<PlayState>>::query().iter(&world).count(), 1);
+        assert_eq!(<Read<LinkDead>>::query().iter(&world).count(), 1);
+        assert_eq!(<Read<InputReceiver>>::query().iter(&world).count(), 0);
+    }
+}
@@ -0,0 +1,140 @@
+//! Out-of-band data exchanged with clients that support GMCP.
+//!
+//! Messages are queued per entity like `Output` and sent by the output
+//! system. Clients that did not negotiate GMCP never see them.
+
+use legion::prelude::*;
+use serde_json::Value;
+use telnet_server::TelnetFrame;
+
+use crate::capabilities::Capabilities;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GmcpMessage {
+    pub package: String,
+    pub data: Value,
+}
+
+impl GmcpMessage {
+    /// Parse a message received from the client. Returns `None` if the data
+    /// is not valid JSON.
+    pub fn parse(package: String, data: &str) -> Option<Self> {
+        let data = if data.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(data).ok()?
+        };
+
+        Some(Self { package, data })
+    }
+
+    pub fn into_frame(self) -> TelnetFrame {
+        let data = match self.data {
+            Value::Null => String::new(),
+            data => data.to_string(),
+        };
+
+        TelnetFrame::Gmcp { package: self.package, data }
+    }
+}
+
+/// GMCP messages waiting to be sent to a player.
+pub struct Gmcp {
+    messages: Vec<GmcpMessage>,
+}
+
+impl Gmcp {
+    pub fn new() -> Self {
+        Self {
+            messages: Vec::with_capacity(2),
+        }
+    }
+
+    pub fn push(&mut self, package: &str, data: Value) {
+        self.messages.push(GmcpMessage { package: package.to_string(), data });
+    }
+}
+
+impl Default for Gmcp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IntoIterator for Gmcp {
+    type Item = GmcpMessage;
+    type IntoIter = std::vec::IntoIter<GmcpMessage>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.messages.into_iter()
+    }
+}
+
+pub trait OptionGmcpExt {
+    fn push_gmcp(&mut self, package: &str, data: Value);
+}
+
+impl OptionGmcpExt for Option<Gmcp> {
+    fn push_gmcp(&mut self, package: &str, data: Value) {
+        let gmcp = self.get_or_insert_with(Default::default);
+        gmcp.push(package, data);
+    }
+}
+
+/// GMCP messages received from a player this tick.
+///
+/// Filled by the protocol system and cleared the next time it runs, so
+/// systems scheduled after it see each message exactly once.
+#[derive(Default)]
+pub struct GmcpInbox {
+    pub messages: Vec<GmcpMessage>,
+}
+
+/// System that answers the `Core` package. `Core.Hello` names the client when
+/// MTTS didn't, and `Core.Ping` is answered with `Core.Ping`. Messages of
+/// other packages are left for the systems that want them.
+pub fn core_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("gmcp_core")
+    .with_query(<(Read<GmcpInbox>, Write<Capabilities>, Write<Option<Gmcp>>)>::query())
+    .build(|_commands, world, _resources, query| {
+        for (inbox, mut capabilities, mut gmcp) in query.iter_mut(world) {
+            for message in &inbox.messages {
+                match message.package.as_str() {
+                    "Core.Hello" if capabilities.client.is_none() => {
+                        capabilities.client = message.data["client"].as_str().map(str::to_uppercase);
+                    },
+
+                    "Core.Ping" => gmcp.push_gmcp("Core.Ping", Value::Null),
+
+                    _ => {},
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn core_messages_are_answered() {
+        let mut world = World::new();
+        let mut resources = Resources::default();
+        let mut schedule = Schedule::builder().add_system(core_system()).build();
+
+        let inbox = GmcpInbox { messages: vec![
+            GmcpMessage { package: "Core.Hello".into(), data: json!({ "client": "Mudlet", "version": "4.10" }) },
+            GmcpMessage { package: "Core.Ping".into(), data: Value::Null },
+        ] };
+        world.insert((), vec![(inbox, Capabilities::default(), None::<Gmcp>)]);
+        schedule.execute(&mut world, &mut resources);
+
+        let client = <Read<Capabilities>>::query().iter(&world).next().unwrap().client.clone();
+        assert_eq!(client.as_deref(), Some("MUDLET"));
+
+        let sent = <Write<Option<Gmcp>>>::query().iter_mut(&mut world).next().unwrap().take().unwrap();
+        assert_eq!(sent.into_iter().map(|message| message.package).collect::<Vec<_>>(), vec!["Core.Ping"]);
+    }
+}
@@ -0,0 +1,146 @@
+//! Disconnecting players who stop sending input.
+//!
+//! How long a player may be idle depends on their `PlayState`. They are
+//! warned shortly before they are disconnected.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use legion::prelude::*;
+use telnet_server::TelnetFrame;
+
+use crate::config::IdleTimeouts;
+use crate::output::{Output, OptionOutputExt};
+use crate::output_queue::OutputSender;
+use crate::play_state::PlayState;
+
+/// When the connection last received a line of input. Shared between the
+/// connection layer, which records input, and the game.
+#[derive(Debug, Clone)]
+pub struct LastInput(Arc<Mutex<Instant>>);
+
+impl LastInput {
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    /// Record input received now.
+    pub fn touch(&self) {
+        *self.0.lock().expect("LastInput lock poisoned.") = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.0.lock().expect("LastInput lock poisoned.").elapsed()
+    }
+}
+
+/// How idle a connection is.
+pub struct Idle {
+    last_input: LastInput,
+    warned: bool,
+    disconnecting: bool,
+}
+
+impl Idle {
+    pub fn new(last_input: LastInput) -> Self {
+        Self { last_input, warned: false, disconnecting: false }
+    }
+}
+
+impl IdleTimeouts {
+    /// The longest a player in `play_state` may be idle. `None` means forever.
+    fn timeout(&self, play_state: PlayState) -> Option<Duration> {
+        let seconds = match play_state {
+            PlayState::Login | PlayState::ChooseCharacter | PlayState::Quitting => self.login,
+            PlayState::Tutorial => self.tutorial,
+            PlayState::Playing => self.playing,
+        };
+
+        if seconds == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(seconds))
+        }
+    }
+
+    /// Time between keepalives. A keepalive that can't be sent within it
+    /// means the client stopped reading.
+    pub fn keepalive_period(&self) -> Duration {
+        Duration::from_secs(self.keepalive.max(1))
+    }
+
+    /// Ticks every keepalive period, starting one period from now. Ignore
+    /// it when keepalives are off.
+    pub fn keepalive_interval(&self) -> tokio::time::Interval {
+        let period = self.keepalive_period();
+        tokio::time::interval_at(tokio::time::Instant::now() + period, period)
+    }
+}
+
+/// System that warns and then disconnects idle players.
+pub fn idle_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("idle")
+    .read_resource::<IdleTimeouts>()
+    .with_query(<(Write<Idle>, Read<PlayState>, Write<Option<Output>>, Write<OutputSender>)>::query())
+    .build(|_commands, world, timeouts, query| {
+        let warning = Duration::from_secs(timeouts.warning);
+
+        for (mut idle, play_state, mut output, mut output_sender) in query.iter_mut(world) {
+            let timeout = match timeouts.timeout(*play_state) {
+                Some(timeout) => timeout,
+                None => continue,
+            };
+
+            if idle.disconnecting {
+                continue;
+            }
+
+            let elapsed = idle.last_input.elapsed();
+
+            if elapsed >= timeout {
+                // Sent directly, since the connection closes before the
+                // output system runs again.
+                output_sender.send(TelnetFrame::Data("\r\nYou have been idle too long. Goodbye.\r\n".into()));
+                output_sender.close();
+                idle.disconnecting = true;
+            } else if elapsed + warning >= timeout {
+                if !idle.warned {
+                    let remaining = (timeout - elapsed).as_secs().max(1);
+                    output.push_paragraph(format!("You have been idle for a while. You will be disconnected in {} seconds unless you send something.", remaining));
+                    idle.warned = true;
+                }
+            } else {
+                idle.warned = false;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::config::OutputQueue;
+    use crate::output_queue;
+
+    #[tokio::test]
+    async fn idle_players_are_warned_then_disconnected() {
+        let mut world = World::new();
+        let mut resources = Resources::default();
+        resources.insert(IdleTimeouts { login: 60, warning: 10, ..IdleTimeouts::default() });
+        let mut schedule = Schedule::builder().add_system(idle_system()).build();
+
+        let last_input = LastInput::new();
+        let (send_output, mut recv_output) = output_queue::channel(OutputQueue::default(), ([127, 0, 0, 1], 5431).into());
+        world.insert((), vec![(Idle::new(last_input.clone()), PlayState::Login, None::<Output>, send_output)]);
+
+        *last_input.0.lock().unwrap() -= Duration::from_secs(55);
+        schedule.execute(&mut world, &mut resources);
+        assert_eq!(<Read<Option<Output>>>::query().iter(&world).filter(|output| output.is_some()).count(), 1);
+
+        *last_input.0.lock().unwrap() -= Duration::from_secs(5);
+        schedule.execute(&mut world, &mut resources);
+        assert!(matches!(recv_output.recv().await, Some(TelnetFrame::Data(_))));
+        assert_eq!(recv_output.recv().await, None);
+    }
+}
@@ -0,0 +1,49 @@
+//! Whether the player's client shows what they type.
+//!
+//! Game states hide input for things like passwords. The server asks the
+//! client to stop echoing by offering to echo itself (WILL ECHO) and then
+//! not doing so. WONT ECHO gives echoing back to the client.
+
+use telnet_server::{TelnetFrame, TelnetOption, Verb};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InputEcho {
+    hidden: bool,
+    /// Whether the client was last told to hide input.
+    sent_hidden: bool,
+}
+
+impl InputEcho {
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.hidden = hidden;
+    }
+
+    /// The negotiation to send if input was hidden or shown since the last call.
+    pub fn take_frame(&mut self) -> Option<TelnetFrame> {
+        if self.hidden == self.sent_hidden {
+            return None;
+        }
+
+        self.sent_hidden = self.hidden;
+        let verb = if self.hidden { Verb::Will } else { Verb::Wont };
+        Some(TelnetFrame::Negotiate(verb, TelnetOption::Echo))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changes_are_sent_once() {
+        let mut echo = InputEcho::default();
+        assert_eq!(echo.take_frame(), None);
+
+        echo.set_hidden(true);
+        assert_eq!(echo.take_frame(), Some(TelnetFrame::Negotiate(Verb::Will, TelnetOption::Echo)));
+        assert_eq!(echo.take_frame(), None);
+
+        echo.set_hidden(false);
+        assert_eq!(echo.take_frame(), Some(TelnetFrame::Negotiate(Verb::Wont, TelnetOption::Echo)));
+    }
+}
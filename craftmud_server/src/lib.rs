@@ -5,14 +5,29 @@ use legion::prelude::*;
 
 mod models;
 
+mod capabilities;
+mod config;
+mod copyover;
+mod craft;
 mod db_config;
+mod disconnect;
+mod gmcp;
+mod idle;
+mod input_echo;
 mod login;
+mod msdp;
+mod mssp;
+mod mxp;
 mod place;
 mod play_state;
 mod prompt;
-mod telnet;
+mod protocol;
+pub mod telnet;
 mod tutorial;
+mod websocket;
+mod window_size;
 mod output;
+mod output_queue;
 mod outside;
 
 // TODO(Havvy, 2019-12-22, #wrong): Parse out whitespace in commands. Take inspiration from Tennu.
@@ -24,8 +39,14 @@ pub fn main() {
 
     // let db = outside::Database {};
 
+    let config = config::Config::load().unwrap_or_else(|err| { eprintln!("{}", err); std::process::exit(1); });
+
     // Start Tokio-driven things.
-    let outside::Outside { database, recv_connection } = outside::start_tokio_runtime();
+    let status = mssp::ServerStatus::new(&config);
+    let idle_timeouts = config.idle;
+    let resumed = copyover::take_resumed();
+
+    let outside::Outside { database, recv_connection, copyover } = outside::start_tokio_runtime(config, status.clone(), resumed).unwrap_or_else(|err| { eprintln!("{}", err); std::process::exit(1); });
 
     println!("Tokio-driven systems are go.");
 
@@ -34,6 +55,9 @@ pub fn main() {
     let mut resources = &mut Resources::default();
     resources.insert(recv_connection);
     resources.insert(database);
+    resources.insert(status);
+    resources.insert(idle_timeouts);
+    resources.insert(copyover);
 
     let (tutorial_realm, tutorial_starting_room) = tutorial::initialize_tutorial(world);
     resources.insert(tutorial_realm);
@@ -42,10 +66,19 @@ pub fn main() {
     let mut schedule = Schedule::builder()
     .add_system(login::add_connection_system())
     .flush()
+    .add_system(disconnect::disconnect_system())
+    .flush()
+    .add_system(protocol::protocol_system())
+    .add_system(gmcp::core_system())
     .add_system(login::login_system(tutorial_starting_room))
     .add_system(tutorial::tutorial_system())
+    .add_system(idle::idle_system())
+    .add_system(craft::progress_system())
     .flush()
     .add_system(login::output_system())
+    .add_system(mssp::status_system())
+    .add_system(output_queue::depth_report_system())
+    .add_system(copyover::save_system())
     .build();
 
     // Run the world.
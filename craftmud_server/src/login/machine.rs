@@ -21,6 +21,8 @@ pub(super) enum HandledByAction {
 pub(super) trait State: std::fmt::Debug + Sized + Into<Machine> {
     const PREAMBLE: Option<&'static str> = None;
     const WAITING_ON_DB: bool = false;
+    /// Whether the input is something like a password that should not be shown.
+    const SENSITIVE_INPUT: bool = false;
 
     type Previous: State + Into<Machine>;
 
@@ -177,6 +179,7 @@ pub(super) struct RegisterRequestPassword(AccountName, Email);
 
 impl State for RegisterRequestPassword {
     const PREAMBLE: Option<&'static str> = Some("What will be your password?\r\n");
+    const SENSITIVE_INPUT: bool = true;
 
     type Previous = RegisterRequestEmail;
 
@@ -225,6 +228,7 @@ pub(super) struct LoginRequestPassword(AccountName);
 
 impl State for LoginRequestPassword {
     const PREAMBLE: Option<&'static str> = Some("What is your password?\r\n");
+    const SENSITIVE_INPUT: bool = true;
 
     type Previous = JustConnected;
 
@@ -276,6 +280,23 @@ impl Default for Machine {
 }
 
 impl Machine {
+    /// Continue logging in after a copyover. Registering might have been
+    /// waiting on the database, so it starts over instead.
+    pub(super) fn resume(account_name: Option<AccountName>) -> Self {
+        match account_name {
+            Some(account_name) => LoginRequestPassword(account_name).into(),
+            None => Self::default(),
+        }
+    }
+
+    /// The account being logged in to, if the user has named one.
+    pub(super) fn account_name(&self) -> Option<&AccountName> {
+        match self {
+            Machine::LoginRequestPassword(LoginRequestPassword(account_name)) => Some(account_name),
+            _ => None,
+        }
+    }
+
     pub fn preamble(&self) -> Option<&'static str> {
         match self {
             Machine::JustConnected(_state) => JustConnected::PREAMBLE,
@@ -302,6 +323,19 @@ impl Machine {
         }
     }
 
+    pub fn sensitive_input(&self) -> bool {
+        match self {
+            Machine::JustConnected(_state) => JustConnected::SENSITIVE_INPUT,
+            Machine::RegisterRequestName(_state) => RegisterRequestName::SENSITIVE_INPUT,
+            Machine::RegisterReqEmail(_state) => RegisterRequestEmail::SENSITIVE_INPUT,
+            Machine::RegisterCheckNameEmailUnique(_state) => RegisterCheckNameEmailUnique::SENSITIVE_INPUT,
+            Machine::RegisterRequestPassword(_state) => RegisterRequestPassword::SENSITIVE_INPUT,
+            Machine::RegisterWaitPasswordInsert(_state) => RegisterWaitPasswordInsert::SENSITIVE_INPUT,
+            Machine::LoginRequestPassword(_state) => LoginRequestPassword::SENSITIVE_INPUT,
+            Machine::Terminal(_state) => panic!("Methods should not be called on terminal login state!"),
+        }
+    }
+
     pub fn handle_input(self, input: String, db: &Database) -> HandledBy {
         match self {
             Machine::JustConnected(state) => State::handle_input(state, input, db),
@@ -2,27 +2,52 @@
 
 // TODO(Havvy, 2019-12-22, #opt): These methods should take in an &mut MudOutput, not create strings.
 
-use std::io::{Cursor};
 use std::ops::DerefMut;
 
 use crossbeam_channel::Receiver;
 use legion::prelude::*;
 
+use std::net::SocketAddr;
+
+use crate::capabilities::Capabilities;
+use crate::copyover::SavedPlayer;
+use crate::gmcp::{Gmcp, GmcpInbox, OptionGmcpExt};
+use crate::idle::{Idle, LastInput};
+use crate::input_echo::InputEcho;
 use crate::models::{Account, UniqueAccountError};
+use crate::msdp::Msdp;
 use crate::output::{Output, OptionOutputExt};
 use crate::outside::Database;
 use crate::place::{PlaceId};
 use crate::play_state::{PlayState};
 use crate::prompt::Prompt;
-use crate::telnet::{Connection, OutputSender, InputReceiver};
+use crate::output_queue::OutputSender;
+use crate::telnet::{Connection, DisconnectReceiver, EventReceiver, InputReceiver};
+use crate::window_size::WindowSize;
+use telnet_server::TelnetFrame;
 
 mod machine;
 
 use machine::{
-    HandledBy, HandledByAction, Terminal,
+    HandledBy, HandledByAction, Machine, Terminal,
 };
 
-use machine::Machine as LoginMachine;
+/// The login state of a user, as a component. Only what copyover needs is
+/// visible outside of logging in.
+#[derive(Debug, Default)]
+pub(crate) struct LoginMachine(Machine);
+
+impl LoginMachine {
+    /// Continue logging in after a copyover.
+    pub(crate) fn resume(account_name: Option<AccountName>) -> Self {
+        Self(Machine::resume(account_name))
+    }
+
+    /// The account being logged in to, if the user has named one.
+    pub(crate) fn account_name(&self) -> Option<&AccountName> {
+        self.0.account_name()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct AccountName(pub String);
@@ -66,29 +91,92 @@ pub fn add_connection_system() -> Box<dyn Schedulable> {
         let recv = resources.deref_mut();
 
         while let Ok(conn) = recv.try_recv() {
+            let Connection { addr, send_output, recv_input, recv_event, recv_disconnect, last_input, resumed } = conn;
+
+            if let Some(player) = resumed {
+                println!("Resuming connection after copyover");
+                resume_player(commands, player, (addr, send_output, recv_input, recv_event, recv_disconnect, last_input));
+                continue;
+            }
+
             println!("Setting up new connection");
-            let Connection { addr, send_output, recv_input } = conn;
             let login = LoginMachine::default();
             let prompt = Prompt::default();
             let play_state = PlayState::Login;
+            let window_size = WindowSize::default();
+            let gmcp: Option<Gmcp> = None;
+            let msdp: Option<Msdp> = None;
+            let capabilities = Capabilities::default();
+            let input_echo = InputEcho::default();
+            let gmcp_inbox = GmcpInbox::default();
+            let idle = Idle::new(last_input);
             let mut output = Output::new();
 
             output.push_static_paragraph(play_state.preamble().expect("Login play state must have a preamble."));
-            output.push_static_paragraph(login.preamble().expect("Default login state must have a preamble."));
+            output.push_static_paragraph(login.0.preamble().expect("Default login state must have a preamble."));
 
-            commands.insert((), vec![(addr, send_output, Some(output), recv_input, login, prompt, play_state,)]);
+            commands.insert((), vec![(addr, send_output, Some(output), recv_input, recv_event, recv_disconnect, login, prompt, play_state, window_size, gmcp, gmcp_inbox, msdp, capabilities, input_echo, idle,)]);
         }
     })
 }
 
+/// Add the entity of a player from before a copyover, with the rest of its
+/// connection.
+fn resume_player(commands: &mut CommandBuffer, player: SavedPlayer, connection: (SocketAddr, OutputSender, InputReceiver, EventReceiver, DisconnectReceiver, LastInput)) {
+    let (addr, send_output, recv_input, recv_event, recv_disconnect, last_input) = connection;
+    let play_state = player.play_state.play_state();
+    let mut input_echo = InputEcho::default();
+    let mut output = Output::new();
+
+    output.push_static_paragraph("The server has restarted. Welcome back!");
+
+    let login = player.login();
+
+    if let Some(ref login) = login {
+        input_echo.set_hidden(login.0.sensitive_input());
+
+        if let Some(preamble) = login.0.preamble() {
+            output.push_static_paragraph(preamble);
+        }
+    }
+
+    let gmcp: Option<Gmcp> = None;
+    let msdp: Option<Msdp> = None;
+
+    let entity = commands.insert((), vec![(addr, send_output, Some(output), recv_input, recv_event, recv_disconnect, Prompt::default(), play_state, player.window_size, gmcp, GmcpInbox::default(), msdp, player.capabilities.clone(), input_echo, Idle::new(last_input),)])[0];
+
+    match (login, player.tutorial()) {
+        (Some(login), _) => commands.add_component(entity, login),
+        (None, Some(tutorial)) => commands.add_component(entity, tutorial),
+        (None, None) => {},
+    }
+}
+
 pub fn output_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("output")
-    .with_query(<(Write<Option<Output>>, Write<OutputSender>, Read<Prompt>)>::query())
+    .with_query(<(Write<Option<Output>>, Write<Option<Gmcp>>, Write<Option<Msdp>>, Write<OutputSender>, Read<Prompt>, Read<Capabilities>, Write<InputEcho>)>::query())
     .build(|_commands, world, _resources, query| {
-        for (mut output, output_sender, prompt) in query.iter_mut(world) {
+        for (mut output, mut gmcp, mut msdp, mut output_sender, prompt, capabilities, mut input_echo) in query.iter_mut(world) {
+            if let Some(frame) = input_echo.take_frame() {
+                output_sender.send(frame);
+            }
+
+            if let Some(gmcp) = std::mem::take(&mut *gmcp) {
+                for message in gmcp {
+                    output_sender.send(message.into_frame());
+                }
+            }
+
+            if let Some(msdp) = std::mem::take(&mut *msdp) {
+                for frame in msdp.into_frames() {
+                    output_sender.send(frame);
+                }
+            }
+
             if let Some(mut output) = std::mem::take(&mut *output) {
                 output.set_prompt(prompt.to_string());
-                output_sender.send(Box::new(Cursor::new(output.to_string())));
+                output.set_mxp(capabilities.mxp);
+                output_sender.send(TelnetFrame::Prompt(output.to_string().into()));
             }
         }
     })
@@ -97,10 +185,10 @@ pub fn output_system() -> Box<dyn Schedulable> {
 pub fn login_system(tutorial_starting_room: PlaceId) -> Box<dyn Schedulable> {
     SystemBuilder::new("login")
     .read_resource::<Database>()
-    .with_query(<(Write<LoginMachine>, Write<Option<Output>>, Write<InputReceiver>, Write<PlayState>, Read<Prompt>)>::query())
+    .with_query(<(Write<LoginMachine>, Write<Option<Output>>, Write<InputReceiver>, Write<PlayState>, Write<InputEcho>, Read<Prompt>, Write<Option<Gmcp>>)>::query())
     .build(move |commands, world, db, query| {
-        for (entity, (mut login_machine_storage, mut output, input_receiver, mut play_state, prompt,),) in query.iter_entities_mut(world) {
-            let login_machine = std::mem::replace(&mut* login_machine_storage, Terminal.into());
+        for (entity, (mut login_machine_storage, mut output, input_receiver, mut play_state, mut input_echo, prompt, mut gmcp,),) in query.iter_entities_mut(world) {
+            let login_machine = std::mem::replace(&mut login_machine_storage.0, Terminal.into());
 
             let HandledBy { machine: login_machine, action } = if login_machine.waiting_on_db() {
                 // Discard any input while waiting on the database.
@@ -116,12 +204,15 @@ pub fn login_system(tutorial_starting_room: PlaceId) -> Box<dyn Schedulable> {
                 HandledByAction::DoNothing => {},
 
                 HandledByAction::InputStateTrans => {
+                    input_echo.set_hidden(login_machine.sensitive_input());
+
                     if let Some(preamble) = login_machine.preamble() {
                         output.push_static_paragraph(preamble);
                     }
                 },
 
                 HandledByAction::InputStateTransWithMessage(message) => {
+                    input_echo.set_hidden(login_machine.sensitive_input());
                     output.push_paragraph(message);
                     if let Some(preamble) = login_machine.preamble() {
                         output.push_static_paragraph(preamble);
@@ -133,6 +224,8 @@ pub fn login_system(tutorial_starting_room: PlaceId) -> Box<dyn Schedulable> {
                 },
 
                 HandledByAction::PlayStateTrans(new_play_state) => {
+                    input_echo.set_hidden(false);
+
                     if let Some(preamble) = new_play_state.preamble() {
                         output.push_static_paragraph(preamble);
                     }
@@ -140,6 +233,8 @@ pub fn login_system(tutorial_starting_room: PlaceId) -> Box<dyn Schedulable> {
                     match new_play_state {
                         PlayState::Tutorial => {
                             *play_state = new_play_state;
+                            gmcp.push_gmcp("Char.Login", serde_json::json!({ "name": null, "state": "tutorial" }));
+                            gmcp.push_gmcp("Char.Vitals", crate::craft::vitals(None));
                             commands.remove_component::<LoginMachine>(entity);
                             commands.add_component(entity, crate::tutorial::Tutorial::new(tutorial_starting_room))
                         },
@@ -148,7 +243,7 @@ pub fn login_system(tutorial_starting_room: PlaceId) -> Box<dyn Schedulable> {
                 }
             };
 
-            std::mem::replace(&mut* login_machine_storage, login_machine);
+            std::mem::replace(&mut login_machine_storage.0, login_machine);
         }
     })
 }
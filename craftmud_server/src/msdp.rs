@@ -0,0 +1,53 @@
+//! Variables published to clients that support MSDP.
+//!
+//! Updates are queued per entity like `Output` and handed to the connection
+//! by the output system. The connection keeps track of which variables the
+//! client asked to have reported and only sends those that changed.
+
+use telnet_server::{MsdpValue, TelnetFrame};
+
+/// Every variable the game publishes, so that clients can REPORT variables
+/// that haven't been published yet, like those of a craft not yet started.
+pub const REPORTABLE: [&str; 11] = [
+    "ROOM_VNUM", "ROOM_NAME", "AREA_NAME", "ROOM_EXITS",
+    "CRAFT_STATE", "CRAFT_PROGRESS", "CRAFT_MAX_PROGRESS", "CRAFT_QUALITY",
+    "CRAFT_MAX_QUALITY", "CRAFT_DURABILITY", "CRAFT_MAX_DURABILITY",
+];
+
+/// MSDP variable updates waiting to be published for a player.
+pub struct Msdp {
+    updates: Vec<(String, MsdpValue)>,
+}
+
+impl Msdp {
+    pub fn new() -> Self {
+        Self {
+            updates: Vec::with_capacity(4),
+        }
+    }
+
+    pub fn set(&mut self, variable: &str, value: MsdpValue) {
+        self.updates.push((variable.to_string(), value));
+    }
+
+    pub fn into_frames(self) -> impl Iterator<Item = TelnetFrame> {
+        self.updates.into_iter().map(|(variable, value)| TelnetFrame::Msdp { variable, value })
+    }
+}
+
+impl Default for Msdp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub trait OptionMsdpExt {
+    fn set_msdp(&mut self, variable: &str, value: MsdpValue);
+}
+
+impl OptionMsdpExt for Option<Msdp> {
+    fn set_msdp(&mut self, variable: &str, value: MsdpValue) {
+        let msdp = self.get_or_insert_with(Default::default);
+        msdp.set(variable, value);
+    }
+}
@@ -0,0 +1,114 @@
+//! The server status reported to MUD listing sites with MSSP.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use legion::prelude::*;
+use telnet_server::MsspVariables;
+
+use crate::config::{Config, Listener};
+use crate::play_state::PlayState;
+use crate::telnet::DisconnectReceiver;
+
+/// Live server status, shared between the game and the connection layer.
+#[derive(Clone)]
+pub struct ServerStatus {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    name: String,
+    started: SystemTime,
+    /// Ports of plain TELNET listeners.
+    ports: Vec<String>,
+    /// Ports of TELNET over TLS listeners.
+    tls_ports: Vec<String>,
+    /// Connected entities, updated by `status_system`.
+    players: AtomicUsize,
+}
+
+impl ServerStatus {
+    /// Status of a server starting now.
+    pub fn new(config: &Config) -> Self {
+        let port = |address: &str| address.rsplit(':').next().unwrap_or(address).to_string();
+
+        let ports = config.listen.iter()
+        .filter_map(|listener| match listener {
+            Listener::Plain { address } => Some(port(address)),
+            _ => None,
+        })
+        .collect();
+
+        let tls_ports = config.listen.iter()
+        .filter_map(|listener| match listener {
+            Listener::Tls { address, .. } => Some(port(address)),
+            _ => None,
+        })
+        .collect();
+
+        Self {
+            shared: Arc::new(Shared {
+                name: config.name.clone(),
+                started: SystemTime::now(),
+                ports,
+                tls_ports,
+                players: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// The MSSP variables for the current status.
+    pub fn variables(&self) -> MsspVariables {
+        let shared = &*self.shared;
+        let uptime = shared.started.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+
+        let mut variables = vec![
+            ("NAME".to_string(), shared.name.clone()),
+            ("PLAYERS".to_string(), shared.players.load(Ordering::Relaxed).to_string()),
+            ("UPTIME".to_string(), uptime.to_string()),
+            ("CODEBASE".to_string(), "CraftMud".to_string()),
+        ];
+
+        variables.extend(shared.ports.iter().map(|port| ("PORT".to_string(), port.clone())));
+        variables.extend(shared.tls_ports.iter().map(|port| ("SSL".to_string(), port.clone())));
+
+        variables
+    }
+}
+
+/// System that counts connected players for the status.
+pub fn status_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("status")
+    .read_resource::<ServerStatus>()
+    .with_query(<(Read<PlayState>, Read<DisconnectReceiver>)>::query())
+    .build(|_commands, world, status, query| {
+        let players = query.iter(world).count();
+        status.shared.players.store(players, Ordering::Relaxed);
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn variables_from_config() {
+        let config = Config {
+            listen: vec![
+                Listener::Plain { address: "0.0.0.0:4000".into() },
+                Listener::Tls { address: "0.0.0.0:4001".into(), certificate: "cert.pem".into(), key: "key.pem".into() },
+                Listener::Websocket { address: "0.0.0.0:4002".into() },
+            ],
+            ..Config::default()
+        };
+
+        let variables = ServerStatus::new(&config).variables();
+
+        assert!(variables.contains(&("NAME".to_string(), "CraftMud".to_string())));
+        assert!(variables.contains(&("PLAYERS".to_string(), "0".to_string())));
+        assert!(variables.contains(&("PORT".to_string(), "4000".to_string())));
+        assert!(variables.contains(&("SSL".to_string(), "4001".to_string())));
+        assert!(!variables.iter().any(|(_, value)| value == "4002"));
+    }
+}
@@ -0,0 +1,188 @@
+//! Text with MUD eXtension Protocol tags.
+//!
+//! https://www.zuggsoft.com/zmud/mxp.htm
+//!
+//! Game code describes what parts of the text are, such as an exit or a
+//! command, and the tags are only written for clients that negotiated MXP.
+//! Every other client gets the same text without them.
+
+use std::borrow::Cow;
+
+/// Puts the rest of the line in secure mode, which allows `<send>` tags.
+const SECURE_LINE: &str = "\x1b[1z";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(Cow<'static, str>),
+    /// An exit of the current place. Clicking it goes through the exit.
+    Exit(String),
+    /// A command. Clicking it sends the command.
+    Command(String),
+}
+
+/// Text with semantic tags.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Markup {
+    segments: Vec<Segment>,
+}
+
+impl Markup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Markup where commands quoted in backticks, like `next`, are clickable.
+    pub fn with_commands(text: &'static str) -> Self {
+        let mut markup = Self::new();
+        let mut parts = text.split('`');
+
+        if let Some(before) = parts.next() {
+            markup.push_text(before);
+        }
+
+        // Parts alternate between commands and text. An unclosed backtick
+        // leaves the rest as text.
+        while let Some(command) = parts.next() {
+            match parts.next() {
+                Some(after) => {
+                    markup.push_text("`");
+                    markup.push_command(command);
+                    markup.push_text("`");
+                    markup.push_text(after);
+                },
+
+                None => {
+                    markup.push_text("`");
+                    markup.push_text(command);
+                },
+            }
+        }
+
+        markup
+    }
+
+    pub fn push_text(&mut self, text: impl Into<Cow<'static, str>>) {
+        let text = text.into();
+
+        if !text.is_empty() {
+            self.segments.push(Segment::Text(text));
+        }
+    }
+
+    pub fn push_exit(&mut self, exit: &str) {
+        self.segments.push(Segment::Exit(exit.to_string()));
+    }
+
+    pub fn push_command(&mut self, command: &str) {
+        self.segments.push(Segment::Command(command.to_string()));
+    }
+
+    /// The text without any tags.
+    pub fn to_plain(&self) -> String {
+        self.segments.iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => &**text,
+            Segment::Exit(text) | Segment::Command(text) => &**text,
+        })
+        .collect()
+    }
+
+    /// The text with MXP tags. Lines with tags are put in secure mode.
+    pub fn to_mxp(&self) -> String {
+        let mut tagged = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => tagged.push_str(&escape(text)),
+
+                Segment::Exit(exit) => {
+                    let exit = escape(exit);
+                    tagged.push_str(&format!("<send href=\"{}\" hint=\"Go {}\">{}</send>", exit, exit, exit));
+                },
+
+                Segment::Command(command) => {
+                    tagged.push_str(&format!("<send>{}</send>", escape(command)));
+                },
+            }
+        }
+
+        // Text is escaped, so any `<` left starts a tag.
+        let mut mxp = String::with_capacity(tagged.len());
+
+        for line in tagged.split_inclusive('\n') {
+            if line.contains('<') {
+                mxp.push_str(SECURE_LINE);
+            }
+
+            mxp.push_str(line);
+        }
+
+        mxp
+    }
+
+    pub fn render(&self, mxp: bool) -> String {
+        if mxp {
+            self.to_mxp()
+        } else {
+            self.to_plain()
+        }
+    }
+}
+
+impl From<&'static str> for Markup {
+    fn from(text: &'static str) -> Self {
+        let mut markup = Self::new();
+        markup.push_text(text);
+        markup
+    }
+}
+
+impl From<String> for Markup {
+    fn from(text: String) -> Self {
+        let mut markup = Self::new();
+        markup.push_text(text);
+        markup
+    }
+}
+
+/// Escape text so that MXP clients show it as is.
+pub fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(&['<', '>', '&', '"'][..]) {
+        return text.into();
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 8);
+
+    for ch in text.chars() {
+        match ch {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            ch => escaped.push(ch),
+        }
+    }
+
+    escaped.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn commands_in_backticks() {
+        let markup = Markup::with_commands("Use `next` now.\r\nOr <don't>.");
+
+        assert_eq!(markup.to_plain(), "Use `next` now.\r\nOr <don't>.");
+        assert_eq!(markup.to_mxp(), "\x1b[1zUse `<send>next</send>` now.\r\nOr &lt;don't&gt;.");
+    }
+
+    #[test]
+    fn unclosed_backtick_is_text() {
+        let markup = Markup::with_commands("a `b");
+
+        assert_eq!(markup.to_plain(), "a `b");
+        assert_eq!(markup.to_mxp(), "a `b");
+    }
+}
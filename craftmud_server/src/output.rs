@@ -1,10 +1,11 @@
 //! Output of the game server to an individual player.
 
-use std::borrow::Cow;
+use crate::mxp::{self, Markup};
 
 pub struct Output {
-    paragraphs: Vec<Cow<'static, str>>,
+    paragraphs: Vec<Markup>,
     prompt: Option<String>,
+    mxp: bool,
 }
 
 impl Output {
@@ -12,25 +13,37 @@ impl Output {
         Self {
             paragraphs: Vec::with_capacity(4),
             prompt: None,
+            mxp: false,
         }
     }
 
+    /// Commands quoted in backticks are made clickable for MXP clients.
     pub fn push_static_paragraph(&mut self, paragraph: &'static str) {
-        self.paragraphs.push(paragraph.into());
+        self.paragraphs.push(Markup::with_commands(paragraph));
     }
 
     pub fn push_paragraph(&mut self, paragraph: String) {
         self.paragraphs.push(paragraph.into());
     }
 
+    pub fn push_markup(&mut self, paragraph: Markup) {
+        self.paragraphs.push(paragraph);
+    }
+
     pub fn set_prompt(&mut self, prompt: String) {
         self.prompt = Some(prompt);
     }
+
+    /// Whether to write MXP tags. Without MXP, tags are stripped.
+    pub fn set_mxp(&mut self, mxp: bool) {
+        self.mxp = mxp;
+    }
 }
 
 pub trait OptionOutputExt {
     fn push_static_paragraph(&mut self, paragraph: &'static str);
     fn push_paragraph(&mut self, paragraph: String);
+    fn push_markup(&mut self, paragraph: Markup);
 }
 
 impl OptionOutputExt for Option<Output> {
@@ -43,6 +56,11 @@ impl OptionOutputExt for Option<Output> {
         let output = self.get_or_insert_with(Default::default);
         output.push_paragraph(paragraph);
     }
+
+    fn push_markup(&mut self, paragraph: Markup) {
+        let output = self.get_or_insert_with(Default::default);
+        output.push_markup(paragraph);
+    }
 }
 
 impl Default for Output {
@@ -56,14 +74,18 @@ impl std::fmt::Display for Output {
         f.write_str("\r\n");
 
         for paragraph in &self.paragraphs {
-            f.write_str(&*paragraph);
+            f.write_str(&paragraph.render(self.mxp));
             f.write_str("\r\n");
         }
 
         if let Some(ref prompt) = self.prompt {
-            f.write_str(prompt);
+            if self.mxp {
+                f.write_str(&mxp::escape(prompt));
+            } else {
+                f.write_str(prompt);
+            }
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,248 @@
+//! Bounded queues of output waiting to be sent to a client.
+//!
+//! A client that stops reading cannot make the server buffer output forever.
+//! Past the high-water mark, output that is not essential, such as GMCP and
+//! MSDP, is dropped. Once the queue is full, the connection is closed.
+//!
+//! How full the queues are is logged every minute while any output is
+//! waiting, for operators watching for slow clients.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use legion::prelude::*;
+use telnet_server::TelnetFrame;
+use tokio::sync::{mpsc, Notify};
+
+use crate::config::OutputQueue;
+use crate::telnet::Output;
+
+struct Shared {
+    addr: SocketAddr,
+    /// Frames sent but not yet received.
+    depth: AtomicUsize,
+    /// Whether the depth has been reported as over the high-water mark.
+    over_high_water_mark: AtomicBool,
+    /// Notified when the queue is full. Notifying with nobody waiting leaves
+    /// a permit behind, so waiters check `depth` against `capacity` too.
+    overflowed: Notify,
+    capacity: usize,
+}
+
+/// Create the output queue of the connection from `addr`.
+pub fn channel(limits: OutputQueue, addr: SocketAddr) -> (OutputSender, OutputReceiver) {
+    let capacity = limits.capacity.max(1);
+    let (sender, receiver) = mpsc::channel(capacity);
+    let shared = Arc::new(Shared {
+        addr,
+        depth: AtomicUsize::new(0),
+        over_high_water_mark: AtomicBool::new(false),
+        overflowed: Notify::new(),
+        capacity,
+    });
+
+    let sender = OutputSender { sender: Some(sender), shared: shared.clone(), high_water_mark: limits.high_water_mark };
+    let receiver = OutputReceiver { receiver, shared, high_water_mark: limits.high_water_mark };
+
+    (sender, receiver)
+}
+
+/// Queues output for a connection.
+pub struct OutputSender {
+    /// Taken by `close`.
+    sender: Option<mpsc::Sender<Output>>,
+    shared: Arc<Shared>,
+    high_water_mark: usize,
+}
+
+impl OutputSender {
+    /// Queue a frame. Frames that are not essential are dropped past the
+    /// high-water mark. If the queue is full, the connection is closed.
+    pub fn send(&mut self, frame: Output) {
+        let sender = match self.sender {
+            Some(ref mut sender) => sender,
+            None => return,
+        };
+
+        let depth = self.shared.depth.load(Ordering::Relaxed);
+
+        if depth >= self.high_water_mark {
+            if !self.shared.over_high_water_mark.swap(true, Ordering::Relaxed) {
+                eprintln!("Output queue for {} is over its high-water mark with {} frames", self.shared.addr, depth);
+            }
+
+            if !is_essential(&frame) {
+                return;
+            }
+        }
+
+        // Counted first so the receiver never takes a frame that isn't counted.
+        self.shared.depth.fetch_add(1, Ordering::Relaxed);
+
+        if let Err(err) = sender.try_send(frame) {
+            self.shared.depth.fetch_sub(1, Ordering::Relaxed);
+
+            // When closed, the connection is already gone.
+            if let mpsc::error::TrySendError::Full(_) = err {
+                self.shared.overflowed.notify();
+            }
+        }
+    }
+
+    /// Close the connection once the output already queued has been sent.
+    /// Later output is dropped.
+    pub fn close(&mut self) {
+        self.sender = None;
+    }
+
+    /// Frames waiting to be sent.
+    pub fn depth(&self) -> usize {
+        self.shared.depth.load(Ordering::Relaxed)
+    }
+}
+
+/// Takes output for a connection off its queue.
+pub struct OutputReceiver {
+    receiver: mpsc::Receiver<Output>,
+    shared: Arc<Shared>,
+    high_water_mark: usize,
+}
+
+impl OutputReceiver {
+    pub async fn recv(&mut self) -> Option<Output> {
+        let frame = self.receiver.recv().await?;
+        let depth = self.shared.depth.fetch_sub(1, Ordering::Relaxed) - 1;
+
+        if depth < self.high_water_mark && self.shared.over_high_water_mark.swap(false, Ordering::Relaxed) {
+            eprintln!("Output queue for {} is back under its high-water mark", self.shared.addr);
+        }
+
+        Some(frame)
+    }
+
+    /// Take a frame without waiting for one.
+    pub fn try_recv(&mut self) -> Option<Output> {
+        let frame = self.receiver.try_recv().ok()?;
+        self.shared.depth.fetch_sub(1, Ordering::Relaxed);
+        Some(frame)
+    }
+
+    /// Completes once the queue overflows while it is still full. Wait on
+    /// this while sending to the client; the connection should then be closed
+    /// since the client is not keeping up.
+    pub async fn overflowed(&self) {
+        loop {
+            self.shared.overflowed.notified().await;
+
+            // The notification may be left from an overflow that has since
+            // been drained.
+            if self.shared.depth.load(Ordering::Relaxed) >= self.shared.capacity {
+                return;
+            }
+        }
+    }
+}
+
+/// How often `depth_report_system` logs.
+const DEPTH_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// System that logs the frames waiting in output queues, and the connection
+/// with the most waiting.
+pub fn depth_report_system() -> Box<dyn Schedulable> {
+    let mut last_report = Instant::now();
+
+    SystemBuilder::new("output_queue_depth_report")
+    .with_query(<Read<OutputSender>>::query())
+    .build(move |_commands, world, _resources, query| {
+        if last_report.elapsed() < DEPTH_REPORT_INTERVAL {
+            return;
+        }
+
+        last_report = Instant::now();
+
+        if let Some((queued, deepest)) = depth_report(query.iter(world).map(|sender| (sender.shared.addr, sender.depth()))) {
+            println!("Output queues: {} frames waiting; the most is {} frames for {}", queued, deepest.1, deepest.0);
+        }
+    })
+}
+
+/// The total frames waiting and the connection with the most, if any are
+/// waiting.
+fn depth_report(depths: impl Iterator<Item = (SocketAddr, usize)>) -> Option<(usize, (SocketAddr, usize))> {
+    let mut queued = 0;
+    let mut deepest = None;
+
+    for (addr, depth) in depths {
+        queued += depth;
+
+        if depth > deepest.map_or(0, |(_, most)| most) {
+            deepest = Some((addr, depth));
+        }
+    }
+
+    deepest.map(|deepest| (queued, deepest))
+}
+
+/// Whether the client needs the frame. Other frames are for client features,
+/// such as maps, and can be skipped.
+fn is_essential(frame: &Output) -> bool {
+    !matches!(frame, TelnetFrame::Gmcp { .. } | TelnetFrame::Msdp { .. })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn non_essential_output_is_dropped_over_the_high_water_mark() {
+        let limits = OutputQueue { capacity: 3, high_water_mark: 1 };
+        let (mut sender, _receiver) = channel(limits, ([127, 0, 0, 1], 5431).into());
+
+        sender.send(TelnetFrame::Data("one".into()));
+        sender.send(TelnetFrame::Gmcp { package: "Room.Info".into(), data: String::new() });
+        assert_eq!(sender.depth(), 1);
+
+        sender.send(TelnetFrame::Data("two".into()));
+        sender.send(TelnetFrame::Data("three".into()));
+        assert_eq!(sender.depth(), 3);
+    }
+
+    #[tokio::test]
+    async fn full_queue_overflows() {
+        let limits = OutputQueue { capacity: 1, high_water_mark: 1 };
+        let (mut sender, mut receiver) = channel(limits, ([127, 0, 0, 1], 5431).into());
+
+        sender.send(TelnetFrame::Data("one".into()));
+        sender.send(TelnetFrame::Data("two".into()));
+
+        let overflowed = tokio::time::timeout(std::time::Duration::from_secs(1), receiver.overflowed()).await;
+        assert!(overflowed.is_ok());
+        assert_eq!(receiver.recv().await, Some(TelnetFrame::Data("one".into())));
+        assert_eq!(sender.depth(), 0);
+    }
+
+    #[test]
+    fn depth_report_names_the_deepest_queue() {
+        let one = ([127, 0, 0, 1], 5431).into();
+        let two = ([127, 0, 0, 2], 5431).into();
+
+        assert_eq!(depth_report(vec![(one, 0), (two, 0)].into_iter()), None);
+        assert_eq!(depth_report(vec![(one, 2), (two, 5)].into_iter()), Some((7, (two, 5))));
+    }
+
+    #[tokio::test]
+    async fn drained_overflow_is_forgotten() {
+        let limits = OutputQueue { capacity: 1, high_water_mark: 1 };
+        let (mut sender, mut receiver) = channel(limits, ([127, 0, 0, 1], 5431).into());
+
+        // Overflow while nothing is waiting, then catch up.
+        sender.send(TelnetFrame::Data("one".into()));
+        sender.send(TelnetFrame::Data("two".into()));
+        assert_eq!(receiver.recv().await, Some(TelnetFrame::Data("one".into())));
+
+        let overflowed = tokio::time::timeout(std::time::Duration::from_millis(100), receiver.overflowed()).await;
+        assert!(overflowed.is_err());
+    }
+}
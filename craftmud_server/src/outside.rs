@@ -8,43 +8,146 @@ use std::thread;
 
 use crossbeam_channel::{self as channel, Sender, Receiver};
 
+use telnet_server::{tls, Limiter, TelnetListener};
+use tokio::io::{Error as TokioIoError, ErrorKind};
+use tokio::net::TcpListener;
 use tokio::runtime::Handle;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio_postgres::{Client, types::ToSql};
 
+use crate::config::{Config, Listener};
+use crate::copyover::{self, Copyover, Resumed};
+use crate::mssp::ServerStatus;
 use crate::telnet;
+use crate::websocket;
 
 type Query = Vec<Box<dyn ToSql + Send + Sync>>;
 
 pub struct Outside {
     pub database: Database,
     pub recv_connection: Receiver<telnet::Connection>,
+    pub copyover: Copyover,
 }
 
-pub fn start_tokio_runtime() -> Outside {
+/// Start the listeners and the database connection, and continue the
+/// connections `resumed` after a copyover.
+///
+/// Fails if any listener cannot be bound, such as when its port is taken.
+pub fn start_tokio_runtime(config: Config, status: ServerStatus, resumed: Vec<Resumed>) -> Result<Outside, String> {
     let (send_connection, recv_connection) = channel::unbounded::<telnet::Connection>();
     let (send_client, recv_client) = channel::unbounded::<Arc<Client>>();
+    let (send_copyover_start, recv_copyover_start) = tokio::sync::mpsc::unbounded_channel();
+    let (send_copyover_request, recv_copyover_request) = channel::unbounded::<copyover::Requested>();
+    let (send_saved, recv_saved) = tokio::sync::mpsc::unbounded_channel();
+    let (send_resumed, recv_resumed) = tokio::sync::mpsc::unbounded_channel();
+    let (detach, _) = tokio::sync::broadcast::channel(1);
 
     let mut runtime = tokio::runtime::Builder::new()
     .basic_scheduler()
     .enable_io()
+    .enable_time()
     .build()
     .expect("Unable to build tokio runtime!");
 
     let handle = runtime.handle().clone();
 
+    let Bound { telnet: telnet_listeners, websocket: websocket_listeners, limiter } = runtime.block_on(bind_listeners(&config))?;
+    let admins = config.admins.clone();
+
     thread::spawn(move || {
         runtime.block_on(async {
-            let telnet_server = telnet::start_telnet_server(send_connection);
+            let websocket_settings = websocket::Settings {
+                limiter,
+                output_queue: config.output_queue,
+                idle: config.idle,
+                detach: detach.clone(),
+            };
+            let websocket_server = websocket::start_websocket_server(send_connection.clone(), websocket_listeners, websocket_settings);
+            let settings = telnet::Settings {
+                output_queue: config.output_queue,
+                idle: config.idle,
+                record: config.record.clone(),
+                status,
+                detach: detach.clone(),
+            };
+
+            for connection in resumed {
+                let _ignore_lack_of_recv = send_resumed.send(connection);
+            }
+
+            let telnet_server = telnet::start_telnet_server(send_connection, telnet_listeners, recv_resumed, settings);
+            let copyover = copyover::copyover_on_request(recv_copyover_start, send_copyover_request, recv_saved, detach, send_resumed);
             let database = start_database(send_client);
 
-            futures::join!(telnet_server, database);
+            futures::join!(telnet_server, websocket_server, copyover, database);
         });
     });
 
     let client = recv_client.recv().expect("Unable to receive database client on startup!");
 
-    Outside { database: Database { handle, client, }, recv_connection, }
+    let copyover = Copyover { recv_request: recv_copyover_request, send_saved, send_start: send_copyover_start, admins };
+
+    Ok(Outside { database: Database { handle, client, }, recv_connection, copyover, })
+}
+
+/// The bound sockets of every listener, by what serves them.
+struct Bound {
+    telnet: Vec<TelnetListener>,
+    websocket: Vec<TcpListener>,
+    /// For WebSocket connections. TELNET listeners have their own clones.
+    limiter: Limiter,
+}
+
+/// Bind every listener up front so that a taken port stops the server
+/// before the game starts.
+async fn bind_listeners(config: &Config) -> Result<Bound, String> {
+    // Shared so that limits apply across every listener together.
+    let limiter = Limiter::new(config.limits.clone().into());
+    let mut bound = Bound { telnet: vec![], websocket: vec![], limiter: limiter.clone() };
+
+    for listener in &config.listen {
+        let address = listener.address();
+        let bind_error = |err: TokioIoError| match err.kind() {
+            ErrorKind::AddrInUse => format!("Unable to listen for {} on {}: the port is already in use", listener.protocol(), address),
+            _ => format!("Unable to listen for {} on {}: {}", listener.protocol(), address, err),
+        };
+
+        let local_addr = match listener {
+            Listener::Plain { .. } => {
+                let mut telnet = TelnetListener::bind(address).await.map_err(bind_error)?;
+                telnet.set_limiter(limiter.clone());
+                telnet.set_trusted_proxies(config.trusted_proxies.clone());
+                let local_addr = telnet.local_addr();
+                bound.telnet.push(telnet);
+                local_addr
+            },
+
+            Listener::Tls { certificate, key, .. } => {
+                let acceptor = tls::load_acceptor(certificate, key)
+                .map_err(|err| format!("Unable to load TLS certificate {} or key {}: {}", certificate.display(), key.display(), err))?;
+                let mut telnet = TelnetListener::bind_tls(address, acceptor).await.map_err(bind_error)?;
+                telnet.set_limiter(limiter.clone());
+                telnet.set_trusted_proxies(config.trusted_proxies.clone());
+                let local_addr = telnet.local_addr();
+                bound.telnet.push(telnet);
+                local_addr
+            },
+
+            Listener::Websocket { .. } => {
+                let websocket = TcpListener::bind(address).await.map_err(bind_error)?;
+                let local_addr = websocket.local_addr();
+                bound.websocket.push(websocket);
+                local_addr
+            },
+        };
+
+        match local_addr {
+            Ok(local_addr) => println!("Listening for {} on {}", listener.protocol(), local_addr),
+            Err(_) => println!("Listening for {} on {}", listener.protocol(), address),
+        }
+    }
+
+    Ok(bound)
 }
 
 pub struct Database {
@@ -6,6 +6,11 @@
 use std::ops::{Index, IndexMut};
 
 use legion::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use telnet_server::MsdpValue;
+
+use crate::mxp::Markup;
 
 pub struct Realm {
     places: Vec<Option<Place>>
@@ -43,7 +48,7 @@ impl IndexMut<PlaceId> for Realm {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PlaceId(usize);
 
 pub struct Place {
@@ -52,16 +57,49 @@ pub struct Place {
 }
 
 impl Place {
-    pub fn look(&self, string: &mut String) {
-        string.push_str(&self.description);
-        string.push_str("\r\nExits: ");
-
-        let exits = &(self.exits.iter()
-            .map(|(exit_name, _)| -> &str { &*exit_name })
-            .collect::<Vec<_>>()
-            .join(", ")
-        );
-        string.push_str(exits);
+    pub fn look(&self, markup: &mut Markup) {
+        markup.push_text(self.description.clone());
+        markup.push_text("\r\nExits: ");
+
+        for (index, (exit_name, _)) in self.exits.iter().enumerate() {
+            if index > 0 {
+                markup.push_text(", ");
+            }
+
+            markup.push_exit(exit_name);
+        }
+    }
+
+    /// The GMCP `Room.Info` data for this place, used by client mappers.
+    pub fn room_info(&self, id: PlaceId, area: &str) -> Value {
+        let name = self.description.lines().next().unwrap_or("");
+
+        let exits = self.exits.iter()
+            .map(|(exit_name, exit_id)| (exit_name.clone(), json!(exit_id.0)))
+            .collect::<Map<String, Value>>();
+
+        json!({
+            "num": id.0,
+            "name": name,
+            "area": area,
+            "exits": exits,
+        })
+    }
+
+    /// The MSDP room variables for this place.
+    pub fn room_variables(&self, id: PlaceId, area: &str) -> Vec<(&'static str, MsdpValue)> {
+        let name = self.description.lines().next().unwrap_or("");
+
+        let exits = self.exits.iter()
+            .map(|(exit_name, exit_id)| (exit_name.clone(), MsdpValue::from(exit_id.0.to_string())))
+            .collect();
+
+        vec![
+            ("ROOM_VNUM", id.0.to_string().into()),
+            ("ROOM_NAME", name.into()),
+            ("AREA_NAME", area.into()),
+            ("ROOM_EXITS", MsdpValue::Table(exits)),
+        ]
     }
 }
 
@@ -71,7 +109,7 @@ mod test {
 
     #[test]
     fn look_in_place() {
-        let mut s = String::new();
+        let mut s = Markup::new();
 
         let p = Place {
             description: "Description".to_string(),
@@ -80,6 +118,26 @@ mod test {
 
         p.look(&mut s);
 
-        assert_eq!(s, "Description\r\nExits: exit");
+        assert_eq!(s.to_plain(), "Description\r\nExits: exit");
+        assert_eq!(s.to_mxp(), "Description\r\n\x1b[1zExits: <send href=\"exit\" hint=\"Go exit\">exit</send>");
+    }
+
+    #[test]
+    fn room_info_of_place() {
+        let mut realm = Realm::new();
+        let here = realm.next_id();
+        let there = realm.next_id();
+
+        let p = Place {
+            description: "A room\r\nWith more description".to_string(),
+            exits: vec![("forward".to_string(), there)],
+        };
+
+        assert_eq!(p.room_info(here, "Tutorial"), json!({
+            "num": 0,
+            "name": "A room",
+            "area": "Tutorial",
+            "exits": { "forward": 1 },
+        }));
     }
 }
\ No newline at end of file
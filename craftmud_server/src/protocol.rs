@@ -0,0 +1,55 @@
+//! Reacting to protocol events from a player's connection.
+
+use legion::prelude::*;
+
+use telnet_server::{OptionChange, Party, TelnetOption};
+
+use crate::capabilities::Capabilities;
+use crate::gmcp::{GmcpInbox, GmcpMessage};
+use crate::telnet::{Event, EventReceiver};
+use crate::window_size::WindowSize;
+
+/// System that applies the protocol events of every connection to its
+/// components.
+pub fn protocol_system() -> Box<dyn Schedulable> {
+    SystemBuilder::new("protocol")
+    .with_query(<(Write<EventReceiver>, Write<WindowSize>, Write<GmcpInbox>, Write<Capabilities>)>::query())
+    .build(|_commands, world, _resources, query| {
+        for (recv_event, mut window_size, mut gmcp_inbox, mut capabilities) in query.iter_mut(world) {
+            gmcp_inbox.messages.clear();
+
+            while let Ok(event) = recv_event.try_recv() {
+                match event {
+                    Event::WindowSize { width, height } => {
+                        window_size.update(width, height);
+                    },
+
+                    Event::Gmcp { package, data } => {
+                        match GmcpMessage::parse(package, &data) {
+                            Some(message) => gmcp_inbox.messages.push(message),
+                            None => eprintln!("Ignoring GMCP message with invalid JSON: {}", data),
+                        }
+                    },
+
+                    Event::TerminalType(terminal_type) => {
+                        capabilities.update_from_terminal_type(terminal_type);
+                    },
+
+                    Event::Charset(charset) => {
+                        capabilities.update_from_charset(charset);
+                    },
+
+                    Event::OptionChange(OptionChange { option: TelnetOption::Mxp, party: Party::Local, enabled }) => {
+                        capabilities.mxp = enabled;
+                    },
+
+                    // Nothing reacts to these yet.
+                    Event::Command(_) | Event::Subnegotiation(..) | Event::OptionChange(_) | Event::Msdp { .. } => {},
+
+                    // Lines and negotiation commands are handled by the connection layer.
+                    Event::Line(_) | Event::Negotiate(..) => {},
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,246 @@
+pub mod recorder;
+
+use crossbeam_channel::{self as channel, Sender, Receiver};
+use futures::{SinkExt as _, StreamExt as _};
+use telnet_server::{ControlCode, TelnetEvent, TelnetFrame, TelnetListener, TelnetStream};
+use tokio::io::{
+    Error as TokioIoError,
+};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::config::{IdleTimeouts, OutputQueue, Recording};
+use crate::copyover::{self, Detach, Detached, Resumed, SavedPlayer};
+use crate::idle::LastInput;
+use crate::mssp::ServerStatus;
+use crate::output_queue::{self, OutputSender};
+
+use self::recorder::Recorder;
+
+pub type Input = String;
+pub type InputReceiver = Receiver<Input>;
+/// Protocol events other than lines of input.
+pub type Event = TelnetEvent;
+pub type EventReceiver = Receiver<Event>;
+pub type Output = TelnetFrame;
+/// Receives `Disconnected` once the connection closes.
+pub type DisconnectReceiver = Receiver<Disconnected>;
+
+/// Sent by the connection layer when the client's connection closes.
+#[derive(Debug)]
+pub struct Disconnected;
+
+/// How long to wait for output to be sent when closing a connection.
+const CLOSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+pub struct Connection {
+    pub addr: std::net::SocketAddr,
+    pub send_output: OutputSender,
+    pub recv_input: InputReceiver,
+    pub recv_event: EventReceiver,
+    pub recv_disconnect: DisconnectReceiver,
+    pub last_input: LastInput,
+    /// The player on this connection before a copyover.
+    pub resumed: Option<SavedPlayer>,
+}
+
+/// What every TELNET connection is run with.
+#[derive(Clone)]
+pub struct Settings {
+    pub output_queue: OutputQueue,
+    pub idle: IdleTimeouts,
+    pub record: Recording,
+    pub status: ServerStatus,
+    /// Subscribed to by every connection.
+    pub detach: broadcast::Sender<Detach>,
+}
+
+/// Accept connections on the plain and TLS listeners, and continue the
+/// connections from `recv_resumed` after a copyover.
+pub async fn start_telnet_server(send_new_connection: Sender<Connection>, listeners: Vec<TelnetListener>, recv_resumed: mpsc::UnboundedReceiver<Resumed>, settings: Settings) -> Result<(), TokioIoError> {
+    println!("Starting telnet server");
+
+    let resuming = resume_connections(recv_resumed, send_new_connection.clone(), settings.clone());
+    let accepting = listeners.into_iter().map(|listener| accept_connections(listener, send_new_connection.clone(), settings.clone()));
+    futures::future::try_join(resuming, futures::future::try_join_all(accepting)).await?;
+
+    Ok(())
+}
+
+async fn resume_connections(mut recv_resumed: mpsc::UnboundedReceiver<Resumed>, send_new_connection: Sender<Connection>, settings: Settings) -> Result<(), TokioIoError> {
+    while let Some(Resumed { addr, tcp, state, player }) = recv_resumed.recv().await {
+        match TelnetStream::resume(tcp, state) {
+            Ok(stream) => {
+                tokio::spawn(run_connection(stream, addr, send_new_connection.clone(), settings.clone(), player));
+            },
+            Err(err) => eprintln!("Unable to resume connection from {}: {}", addr, err),
+        }
+    }
+
+    Ok(())
+}
+
+async fn accept_connections(mut listener: TelnetListener, send_new_connection: Sender<Connection>, settings: Settings) -> Result<(), TokioIoError> {
+    loop {
+        let (accepted, peer_addr) = listener.accept().await?;
+        let send_new_connection = send_new_connection.clone();
+        let settings = settings.clone();
+
+        tokio::spawn(async move {
+            match accepted.handshake().await {
+                Ok((stream, addr)) => run_connection(stream, addr, send_new_connection, settings, None).await,
+                Err(err) => eprintln!("Handshake with {} failed: {}", peer_addr, err),
+            }
+        });
+    }
+}
+
+/// Start recording the connection from `addr`. Failing to is logged and the
+/// connection goes unrecorded.
+fn start_recording(directory: &std::path::Path, addr: std::net::SocketAddr) -> Option<Recorder> {
+    match Recorder::create(directory, addr) {
+        Ok(recorder) => {
+            println!("Recording connection from {} to {}", addr, recorder.path().display());
+            Some(recorder)
+        },
+        Err(err) => {
+            eprintln!("Unable to record connection from {}: {}", addr, err);
+            None
+        },
+    }
+}
+
+/// Record with `record`, giving up on recording the connection if it fails.
+fn record(recorder: &mut Option<Recorder>, addr: std::net::SocketAddr, record: impl FnOnce(&mut Recorder) -> Result<(), std::io::Error>) {
+    if let Some(Err(err)) = recorder.as_mut().map(record) {
+        eprintln!("Stopped recording connection from {}: {}", addr, err);
+        *recorder = None;
+    }
+}
+
+/// Hand the connection to the game and pass data between them until either
+/// side is done.
+async fn run_connection(mut stream: TelnetStream, addr: std::net::SocketAddr, send_new_connection: Sender<Connection>, settings: Settings, resumed: Option<SavedPlayer>) {
+    let mut detach = settings.detach.subscribe();
+    let idle = settings.idle;
+
+    let status = settings.status.clone();
+    stream.serve_mssp(move || status.variables());
+    stream.set_msdp_reportable(&crate::msdp::REPORTABLE);
+    let mut recorder = settings.record.directory_for(addr.ip()).and_then(|directory| start_recording(directory, addr));
+
+    let (send_output, mut recv_output) = output_queue::channel(settings.output_queue, addr);
+    let (send_input, recv_input) = channel::unbounded::<Input>();
+    let (send_event, recv_event) = channel::unbounded::<Event>();
+    let (send_disconnect, recv_disconnect) = channel::bounded::<Disconnected>(1);
+    let last_input = LastInput::new();
+
+    let new_connection = Connection {
+        addr, send_output, recv_input, recv_event, recv_disconnect, last_input: last_input.clone(), resumed,
+    };
+
+    let _ignore_lack_of_recv = send_new_connection.send(new_connection);
+
+    let mut keepalive = idle.keepalive_interval();
+
+    loop {
+        tokio::select! {
+            event = stream.next() => match event {
+                Some(Ok(event)) => {
+                    record(&mut recorder, addr, |recorder| recorder.input(&event));
+
+                    match event {
+                        TelnetEvent::Line(input) => {
+                            last_input.touch();
+                            let _ignore_lack_of_recv = send_input.send(input);
+                        },
+                        event => {
+                            let _ignore_lack_of_recv = send_event.send(event);
+                        },
+                    }
+                },
+                Some(Err(err)) => {
+                    eprintln!("Closing connection from {}: {}", addr, err);
+                    break;
+                },
+                None => break,
+            },
+
+            output = recv_output.recv() => match output {
+                Some(output) => {
+                    record(&mut recorder, addr, |recorder| recorder.output(&output));
+
+                    tokio::select! {
+                        result = stream.send(output) => if result.is_err() {
+                            break;
+                        },
+                        _ = recv_output.overflowed() => {
+                            eprintln!("Closing connection from {}: it is not reading its output", addr);
+                            break;
+                        },
+                    }
+                },
+                None => break,
+            },
+
+            Ok(Detach(send_detached)) = detach.recv() => {
+                // TLS connections are closed by the exec instead, so that the
+                // player keeps playing if it fails.
+                if !stream.can_detach() {
+                    let _ignore_lack_of_recv = send_detached.send(None);
+
+                    let notice = TelnetFrame::Data(copyover::RESTARTING_WITHOUT_HANDOVER.into());
+                    record(&mut recorder, addr, |recorder| recorder.output(&notice));
+
+                    match tokio::time::timeout(CLOSE_TIMEOUT, stream.send(notice)).await {
+                        Ok(Ok(())) => continue,
+                        _ => break,
+                    }
+                }
+
+                // Output queued before the copyover is sent by this server,
+                // followed by why there is a pause.
+                let notice = TelnetFrame::Data(copyover::RESTARTING.into());
+                let queued = std::iter::from_fn(|| recv_output.try_recv()).chain(std::iter::once(notice));
+
+                for output in queued {
+                    record(&mut recorder, addr, |recorder| recorder.output(&output));
+
+                    if stream.send(output).await.is_err() {
+                        break;
+                    }
+                }
+
+                match stream.detach().await {
+                    Ok((tcp, state)) => {
+                        let _ignore_lack_of_recv = send_detached.send(Some(Detached { addr, tcp, state }));
+                    },
+                    Err(err) => {
+                        eprintln!("Closing connection from {} for copyover: {}", addr, err);
+                        let _ignore_lack_of_recv = send_detached.send(None);
+                    },
+                }
+
+                let _ignore_lack_of_recv = send_disconnect.send(Disconnected);
+                return;
+            },
+
+            // Writing to a half-open connection eventually fails, ending it.
+            // A client that stopped reading leaves the write waiting instead.
+            _ = keepalive.tick(), if idle.keepalive > 0 => {
+                match tokio::time::timeout(idle.keepalive_period(), stream.send(TelnetFrame::Command(ControlCode::NOP))).await {
+                    Ok(Ok(())) => {},
+                    Ok(Err(_)) => break,
+                    Err(_) => {
+                        eprintln!("Closing connection from {}: it is not reading its keepalives", addr);
+                        break;
+                    },
+                }
+            },
+        }
+    }
+
+    // Closing sends anything left, such as why a limit closed the connection.
+    // Don't wait forever on a client that stopped reading.
+    let _ = tokio::time::timeout(CLOSE_TIMEOUT, stream.close()).await;
+    let _ignore_lack_of_recv = send_disconnect.send(Disconnected);
+}
@@ -0,0 +1,284 @@
+//! Recording TELNET sessions to files, for reproducing bugs.
+//!
+//! A recording is JSON Lines: one JSON object per line, each with a `kind`.
+//! The first line starts the session and the rest are frames in the order
+//! they happened. `t` is milliseconds since the session started.
+//!
+//! ```text
+//! {"kind":"start","address":"127.0.0.1:50312","unix_ms":1602892800000}
+//! {"kind":"output","t":3,"text":"Welcome to CraftMud!\r\n","prompt":false}
+//! {"kind":"output","t":3,"text":"Name: ","prompt":true}
+//! {"kind":"input","t":2150,"text":"havvy"}
+//! {"kind":"hidden_input","t":4821}
+//! {"kind":"event","t":5002,"event":{"height":24,"type":"window_size","width":80}}
+//! {"kind":"frame","t":5010,"frame":{"data":{"craft":null},"package":"Char.Vitals","type":"gmcp"}}
+//! ```
+//!
+//! * `input` is a line the client sent.
+//! * `hidden_input` is a line sent while the server was hiding input, such
+//!   as a password. Its text is not recorded.
+//! * `output` is text sent to the client. `prompt` is whether it ends in a
+//!   prompt.
+//! * `event` and `frame` are anything else received or sent, as a JSON object
+//!   whose `type` names the kind of event or frame. Commands and options are
+//!   named as in Rust. They are for reading, not for replaying. The data of
+//!   GMCP `Char.Login` messages is left out like hidden input, and the
+//!   message has `"hidden": true` instead.
+//!
+//! Recordings can only be read by the user the server runs as.
+//!
+//! The `replay` binary prints recordings and plays them against a server.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Error as IoError, ErrorKind, Write as _};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use telnet_server::{MsdpValue, TelnetEvent, TelnetFrame, TelnetOption, Verb};
+
+/// GMCP packages whose data is left out, since it can hold passwords.
+const HIDDEN_GMCP: &str = "Char.Login";
+
+/// One line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+    Start { address: String, unix_ms: u64 },
+    Input { t: u64, text: String },
+    HiddenInput { t: u64 },
+    Output { t: u64, text: String, prompt: bool },
+    Event { t: u64, event: Value },
+    Frame { t: u64, frame: Value },
+}
+
+impl Entry {
+    /// Milliseconds since the session started.
+    pub fn t(&self) -> u64 {
+        match self {
+            Entry::Start { .. } => 0,
+            Entry::Input { t, .. } | Entry::HiddenInput { t } | Entry::Output { t, .. } | Entry::Event { t, .. } | Entry::Frame { t, .. } => *t,
+        }
+    }
+}
+
+/// Writes the recording of one connection.
+pub struct Recorder {
+    file: BufWriter<File>,
+    path: PathBuf,
+    started: Instant,
+    /// Whether the server has asked to echo, which it does to hide input.
+    hiding_input: bool,
+}
+
+impl Recorder {
+    /// Start recording the connection from `addr` to a new file in
+    /// `directory`.
+    pub fn create(directory: &Path, addr: SocketAddr) -> Result<Self, IoError> {
+        let unix_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_millis() as u64).unwrap_or(0);
+
+        // Colons aren't allowed in file names everywhere.
+        let name = format!("{}-{}.jsonl", unix_ms, addr.to_string().replace(':', "_"));
+        let path = directory.join(name);
+
+        std::fs::create_dir_all(directory)?;
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let file = BufWriter::new(options.open(&path)?);
+
+        let mut recorder = Self { file, path, started: Instant::now(), hiding_input: false };
+        recorder.write(&Entry::Start { address: addr.to_string(), unix_ms })?;
+        Ok(recorder)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Record something received from the client.
+    pub fn input(&mut self, event: &TelnetEvent) -> Result<(), IoError> {
+        let t = self.elapsed();
+
+        let entry = match event {
+            TelnetEvent::Line(_) if self.hiding_input => Entry::HiddenInput { t },
+            TelnetEvent::Line(text) => Entry::Input { t, text: text.clone() },
+            event => Entry::Event { t, event: event_json(event) },
+        };
+
+        self.write(&entry)
+    }
+
+    /// Record something sent to the client.
+    pub fn output(&mut self, frame: &TelnetFrame) -> Result<(), IoError> {
+        let t = self.elapsed();
+
+        let entry = match frame {
+            TelnetFrame::Data(text) => Entry::Output { t, text: String::from_utf8_lossy(text).into_owned(), prompt: false },
+            TelnetFrame::Prompt(text) => Entry::Output { t, text: String::from_utf8_lossy(text).into_owned(), prompt: true },
+            frame => {
+                match frame {
+                    TelnetFrame::Negotiate(Verb::Will, TelnetOption::Echo) => self.hiding_input = true,
+                    TelnetFrame::Negotiate(Verb::Wont, TelnetOption::Echo) => self.hiding_input = false,
+                    _ => {},
+                }
+
+                Entry::Frame { t, frame: frame_json(frame) }
+            },
+        };
+
+        self.write(&entry)
+    }
+
+    fn elapsed(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    /// Entries are flushed as they are written so that the recording
+    /// survives a crash.
+    fn write(&mut self, entry: &Entry) -> Result<(), IoError> {
+        serde_json::to_writer(&mut self.file, entry)?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()
+    }
+}
+
+fn event_json(event: &TelnetEvent) -> Value {
+    match event {
+        TelnetEvent::Line(text) => json!({ "type": "line", "text": text }),
+        TelnetEvent::Command(command) => json!({ "type": "command", "command": format!("{:?}", command) }),
+        TelnetEvent::Negotiate(verb, option) => negotiate_json(*verb, *option),
+        TelnetEvent::Subnegotiation(option, payload) => subnegotiation_json(*option, payload),
+        TelnetEvent::OptionChange(change) => json!({
+            "type": "option_change",
+            "option": format!("{:?}", change.option),
+            "party": format!("{:?}", change.party),
+            "enabled": change.enabled,
+        }),
+        TelnetEvent::WindowSize { width, height } => json!({ "type": "window_size", "width": width, "height": height }),
+        TelnetEvent::TerminalType(terminal_type) => json!({
+            "type": "terminal_type",
+            "client": terminal_type.client,
+            "terminal": terminal_type.terminal,
+            "mtts": terminal_type.mtts.map(|mtts| mtts.0),
+        }),
+        TelnetEvent::Charset(charset) => json!({ "type": "charset", "charset": charset.name() }),
+        TelnetEvent::Gmcp { package, data } => gmcp_json(package, data),
+        TelnetEvent::Msdp { variable, value } => json!({ "type": "msdp", "variable": variable, "value": msdp_json(value) }),
+    }
+}
+
+fn frame_json(frame: &TelnetFrame) -> Value {
+    match frame {
+        TelnetFrame::Data(text) => json!({ "type": "data", "text": String::from_utf8_lossy(text) }),
+        TelnetFrame::Prompt(text) => json!({ "type": "prompt", "text": String::from_utf8_lossy(text) }),
+        TelnetFrame::Command(command) => json!({ "type": "command", "command": format!("{:?}", command) }),
+        TelnetFrame::Negotiate(verb, option) => negotiate_json(*verb, *option),
+        TelnetFrame::Subnegotiation(option, payload) => subnegotiation_json(*option, payload),
+        TelnetFrame::Gmcp { package, data } => gmcp_json(package, data),
+        TelnetFrame::Msdp { variable, value } => json!({ "type": "msdp", "variable": variable, "value": msdp_json(value) }),
+    }
+}
+
+fn negotiate_json(verb: Verb, option: TelnetOption) -> Value {
+    json!({ "type": "negotiate", "verb": format!("{:?}", verb), "option": format!("{:?}", option) })
+}
+
+fn subnegotiation_json(option: TelnetOption, payload: &[u8]) -> Value {
+    json!({ "type": "subnegotiation", "option": format!("{:?}", option), "payload": payload })
+}
+
+fn gmcp_json(package: &str, data: &str) -> Value {
+    if package.starts_with(HIDDEN_GMCP) {
+        return json!({ "type": "gmcp", "package": package, "hidden": true });
+    }
+
+    // Data that isn't valid JSON is kept as a string.
+    let data = match data {
+        "" => Value::Null,
+        data => serde_json::from_str(data).unwrap_or_else(|_| Value::String(data.to_string())),
+    };
+
+    json!({ "type": "gmcp", "package": package, "data": data })
+}
+
+fn msdp_json(value: &MsdpValue) -> Value {
+    match value {
+        MsdpValue::String(string) => Value::String(string.clone()),
+        MsdpValue::Array(values) => Value::Array(values.iter().map(msdp_json).collect()),
+        MsdpValue::Table(entries) => Value::Object(entries.iter().map(|(name, value)| (name.clone(), msdp_json(value))).collect::<Map<_, _>>()),
+    }
+}
+
+/// Read a recording written by `Recorder`.
+pub fn read(path: &Path) -> Result<Vec<Entry>, IoError> {
+    let file = BufReader::new(File::open(path)?);
+
+    file.lines()
+    .enumerate()
+    .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+    .map(|(index, line)| {
+        let line = line?;
+        serde_json::from_str(&line).map_err(|err| IoError::new(ErrorKind::InvalidData, format!("line {}: {}", index + 1, err)))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hidden_input_is_not_recorded() {
+        let directory = std::env::temp_dir().join(format!("craftmud-recorder-{}", std::process::id()));
+        let mut recorder = Recorder::create(&directory, ([127, 0, 0, 1], 5431).into()).unwrap();
+
+        recorder.output(&TelnetFrame::Prompt("Name: ".into())).unwrap();
+        recorder.input(&TelnetEvent::Line("havvy".into())).unwrap();
+        recorder.output(&TelnetFrame::Negotiate(Verb::Will, TelnetOption::Echo)).unwrap();
+        recorder.input(&TelnetEvent::Line("hunter2".into())).unwrap();
+        recorder.output(&TelnetFrame::Negotiate(Verb::Wont, TelnetOption::Echo)).unwrap();
+        recorder.input(&TelnetEvent::Line("look".into())).unwrap();
+        recorder.input(&TelnetEvent::Gmcp { package: "Char.Login.Credentials".into(), data: r#"{"password": "hunter2"}"#.into() }).unwrap();
+        recorder.input(&TelnetEvent::WindowSize { width: 80, height: 24 }).unwrap();
+
+        let path = recorder.path().to_path_buf();
+        drop(recorder);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt as _;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let entries = read(&path).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let kinds = entries.iter().map(|entry| match entry {
+            Entry::Start { address, .. } => format!("start {}", address),
+            Entry::Input { text, .. } => format!("input {}", text),
+            Entry::HiddenInput { .. } => "hidden".to_string(),
+            Entry::Output { text, prompt, .. } => format!("output {} {}", text, prompt),
+            Entry::Event { event, .. } => event.to_string(),
+            Entry::Frame { frame, .. } => frame.to_string(),
+        }).collect::<Vec<_>>();
+
+        assert_eq!(kinds, vec![
+            "start 127.0.0.1:5431",
+            "output Name:  true",
+            "input havvy",
+            r#"{"option":"Echo","type":"negotiate","verb":"Will"}"#,
+            "hidden",
+            r#"{"option":"Echo","type":"negotiate","verb":"Wont"}"#,
+            "input look",
+            r#"{"hidden":true,"package":"Char.Login.Credentials","type":"gmcp"}"#,
+            r#"{"height":24,"type":"window_size","width":80}"#,
+        ]);
+    }
+}
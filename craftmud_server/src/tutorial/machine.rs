@@ -1,5 +1,6 @@
 use derive_more::From as DeriveFrom;
 
+use crate::mxp::Markup;
 use crate::play_state::PlayState;
 use super::{Data, TutorialRealm};
 use super::messages;
@@ -14,7 +15,7 @@ pub(super) enum HandledByAction {
     InputStateTrans,
     InputStateTransWithMessage(String),
     DoNothing,
-    OutputMessage(String),
+    OutputMessage(Markup),
 }
 
 pub(super) trait State: std::fmt::Debug + Sized + Into<Machine> {
@@ -45,6 +46,19 @@ impl Machine {
         }
     }
 
+    /// Continue the tutorial at `step` after a copyover.
+    pub fn resume(step: usize) -> Self {
+        Self::Intro(Intro(step))
+    }
+
+    /// How far into the tutorial the player is.
+    pub fn step(&self) -> usize {
+        match self {
+            Machine::Intro(Intro(step)) => *step,
+            Machine::Terminal(_) => 0,
+        }
+    }
+
     pub fn take(&mut self) -> Self {
         std::mem::replace(self, Self::Terminal(Terminal))
     }
@@ -62,14 +76,14 @@ impl State for Intro {
         match &*input {
             "next" => HandledBy {
                 machine: Intro(1).into(),
-                action: HandledByAction::OutputMessage(Self::MESSAGES[0].into()),
+                action: HandledByAction::OutputMessage(Markup::with_commands(Self::MESSAGES[0])),
             },
 
             _ => {
                 if self.0 == 0 {
                     HandledBy {
                         machine: self.into(),
-                        action: HandledByAction::OutputMessage(Self::USE_NEXT.into()),
+                        action: HandledByAction::OutputMessage(Markup::with_commands(Self::USE_NEXT)),
                     }
                 } else {
                     HandledBy {
@@ -114,8 +128,8 @@ impl State for Terminal {
     }
 }
 
-fn look_enabled(data: &Data, realm: &TutorialRealm) -> String {
-    let mut s = String::new();
-    realm.0[data.place].look(&mut s);
-    s
+fn look_enabled(data: &Data, realm: &TutorialRealm) -> Markup {
+    let mut markup = Markup::new();
+    realm.0[data.place].look(&mut markup);
+    markup
 }
\ No newline at end of file
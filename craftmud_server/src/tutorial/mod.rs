@@ -1,7 +1,11 @@
 use std::io::{Cursor};
+use std::net::SocketAddr;
 
 use legion::prelude::*;
 
+use crate::copyover::Copyover;
+use crate::gmcp::{Gmcp, OptionGmcpExt};
+use crate::msdp::{Msdp, OptionMsdpExt};
 use crate::output::{Output, OptionOutputExt};
 use crate::place::{Place, PlaceId, Realm};
 use crate::play_state::{PlayState};
@@ -48,24 +52,61 @@ pub struct Tutorial {
 
 struct Data {
     place: PlaceId,
+    /// The place last sent to the client as GMCP `Room.Info` and MSDP
+    /// room variables.
+    room_info_sent: Option<PlaceId>,
 }
 
 impl Tutorial {
     pub fn new(starting_room: PlaceId) -> Self {
         Self {
-            data: Data { place: starting_room, },
+            data: Data { place: starting_room, room_info_sent: None, },
             machine: Machine::new(),
         }
     }
+
+    /// Continue the tutorial after a copyover.
+    pub fn resume(place: PlaceId, step: usize) -> Self {
+        Self {
+            data: Data { place, room_info_sent: None, },
+            machine: Machine::resume(step),
+        }
+    }
+
+    pub fn place(&self) -> PlaceId {
+        self.data.place
+    }
+
+    /// How far into the tutorial the player is.
+    pub fn step(&self) -> usize {
+        self.machine.step()
+    }
 }
 
 pub fn tutorial_system() -> Box<dyn Schedulable> {
     SystemBuilder::new("tutorial")
     .read_resource::<TutorialRealm>()
-    .with_query(<(Write<Tutorial>, Write<InputReceiver>, Write<Option<Output>>, Write<PlayState>, Read<Prompt>)>::query())
-    .build(|commands, world, realm, query| {
-        for (entity, (mut tutorial, input, mut output, mut play_state, prompt,),) in query.iter_entities_mut(world) {
+    .read_resource::<Copyover>()
+    .with_query(<(Write<Tutorial>, Write<InputReceiver>, Write<Option<Output>>, Write<Option<Gmcp>>, Write<Option<Msdp>>, Write<PlayState>, Read<Prompt>, Read<SocketAddr>)>::query())
+    .build(|commands, world, (realm, copyover), query| {
+        for (entity, (mut tutorial, input, mut output, mut gmcp, mut msdp, mut play_state, prompt, addr,),) in query.iter_entities_mut(world) {
+            let place = tutorial.data.place;
+
+            if tutorial.data.room_info_sent != Some(place) {
+                gmcp.push_gmcp("Room.Info", realm.0[place].room_info(place, "Tutorial"));
+                for (variable, value) in realm.0[place].room_variables(place, "Tutorial") {
+                    msdp.set_msdp(variable, value);
+                }
+                tutorial.data.room_info_sent = Some(place);
+            }
+
             if let Ok(input) = input.try_recv() {
+                // Admin commands aren't part of the tutorial.
+                if input == "copyover" && copyover.allows(*addr) {
+                    output.push_static_paragraph(copyover.start());
+                    continue;
+                }
+
                 let Tutorial {
                     ref mut machine,
                     ref mut data
@@ -77,7 +118,7 @@ pub fn tutorial_system() -> Box<dyn Schedulable> {
 
                 match action {
                     machine::HandledByAction::OutputMessage(message) => {
-                        output.push_paragraph(message);
+                        output.push_markup(message);
                     },
 
                     machine::HandledByAction::PlayStateTrans(new_play_state) => {
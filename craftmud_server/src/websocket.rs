@@ -0,0 +1,343 @@
+//! WebSocket listener for browser clients.
+//!
+//! Each session becomes a `Connection` just like a TELNET connection, so the
+//! game cannot tell them apart.
+//!
+//! Without a subprotocol, text frames carry lines of input and output.
+//!
+//! With the `craftmud.json` subprotocol, every text frame is a JSON object:
+//!
+//! * `{"text": "look"}` is a line of input or output. Output that ends in a
+//!   prompt also has `"prompt": true`.
+//! * `{"gmcp": "Room.Info", "data": {...}}` is a GMCP message in either
+//!   direction. `data` is left out when there is none.
+//! * `{"echo": false}` asks the client to hide what is typed, such as
+//!   passwords. `{"echo": true}` shows it again.
+//!
+//! Connections are held to the same `Limits` as TELNET connections, counted
+//! together with them. A message may be no longer than a line of input plus
+//! room for its JSON.
+//!
+//! WebSockets can't be handed over by a copyover. Players are told, and the
+//! connection is closed when the server execs.
+
+use std::net::SocketAddr;
+
+use crossbeam_channel::{self as channel, Sender};
+use futures::{SinkExt as _, StreamExt as _};
+use serde_json::{json, Value};
+use telnet_server::{LimitExceeded, Limiter, Rate, Slot, TelnetFrame, TelnetOption, Verb, DEFAULT_HANDSHAKE_TIMEOUT};
+use tokio::io::Error as TokioIoError;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::HeaderValue,
+    protocol::WebSocketConfig,
+    Message,
+};
+
+use crate::config::{IdleTimeouts, OutputQueue};
+use crate::copyover::{self, Detach};
+use crate::idle::LastInput;
+use crate::output_queue;
+use crate::telnet::{Connection, Disconnected, Event, Input, Output};
+
+/// The subprotocol for JSON messages.
+const JSON_PROTOCOL: &str = "craftmud.json";
+
+/// Bytes a message may have beyond the line length, for its JSON.
+const JSON_OVERHEAD: usize = 1024;
+
+/// How long to wait on a client to take a message about a copyover.
+const COPYOVER_NOTICE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// What every WebSocket connection is run with.
+#[derive(Clone)]
+pub struct Settings {
+    pub limiter: Limiter,
+    pub output_queue: OutputQueue,
+    pub idle: IdleTimeouts,
+    /// Subscribed to by every connection to hear about copyovers.
+    pub detach: broadcast::Sender<Detach>,
+}
+
+pub async fn start_websocket_server(send_new_connection: Sender<Connection>, listeners: Vec<TcpListener>, settings: Settings) -> Result<(), TokioIoError> {
+    if listeners.is_empty() {
+        return Ok(());
+    }
+
+    println!("Starting websocket server");
+
+    let accepting = listeners.into_iter().map(|listener| accept_connections(listener, send_new_connection.clone(), settings.clone()));
+    futures::future::try_join_all(accepting).await?;
+
+    Ok(())
+}
+
+async fn accept_connections(mut listener: TcpListener, send_new_connection: Sender<Connection>, settings: Settings) -> Result<(), TokioIoError> {
+    loop {
+        let (tcp, addr) = listener.accept().await?;
+
+        // Refused before the handshake, so there is no way to say why.
+        let slot = match settings.limiter.accept().and_then(|()| settings.limiter.admit(addr.ip())) {
+            Ok(slot) => slot,
+            Err(exceeded) => {
+                eprintln!("Refusing websocket connection from {}: {}", addr, exceeded);
+                continue;
+            },
+        };
+
+        let send_new_connection = send_new_connection.clone();
+
+        tokio::spawn(run_connection(tcp, addr, slot, send_new_connection, settings.clone()));
+    }
+}
+
+/// Do the WebSocket handshake, then hand the connection to the game and pass
+/// messages between them until either side is done. The connection counts
+/// against its address until `_slot` is dropped at the end.
+async fn run_connection(tcp: TcpStream, addr: SocketAddr, _slot: Slot, send_new_connection: Sender<Connection>, settings: Settings) {
+    let mut detach = settings.detach.subscribe();
+    let idle = settings.idle;
+    let limits = settings.limiter.limits();
+    let mut json = false;
+
+    // The error type is set by tungstenite.
+    #[allow(clippy::result_large_err)]
+    let choose_protocol = |request: &Request, mut response: Response| -> Result<Response, ErrorResponse> {
+        let offered = request.headers().get_all("Sec-WebSocket-Protocol").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|protocol| protocol.trim() == JSON_PROTOCOL);
+
+        if offered {
+            json = true;
+            response.headers_mut().insert("Sec-WebSocket-Protocol", HeaderValue::from_static(JSON_PROTOCOL));
+        }
+
+        Ok(response)
+    };
+
+    let config = WebSocketConfig {
+        max_message_size: Some(limits.line_length + JSON_OVERHEAD),
+        max_frame_size: Some(limits.line_length + JSON_OVERHEAD),
+        ..WebSocketConfig::default()
+    };
+
+    let handshake = tokio_tungstenite::accept_hdr_async_with_config(tcp, choose_protocol, Some(config));
+
+    let mut websocket = match tokio::time::timeout(DEFAULT_HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(Ok(websocket)) => websocket,
+        Ok(Err(err)) => {
+            eprintln!("WebSocket handshake with {} failed: {}", addr, err);
+            return;
+        },
+        Err(_) => {
+            eprintln!("WebSocket handshake with {} timed out", addr);
+            return;
+        },
+    };
+
+    println!("New websocket connection");
+
+    let (send_output, mut recv_output) = output_queue::channel(settings.output_queue, addr);
+    let (send_input, recv_input) = channel::unbounded::<Input>();
+    let (send_event, recv_event) = channel::unbounded::<Event>();
+    let (send_disconnect, recv_disconnect) = channel::bounded::<Disconnected>(1);
+    let last_input = LastInput::new();
+
+    let new_connection = Connection {
+        addr, send_output, recv_input, recv_event, recv_disconnect, last_input: last_input.clone(), resumed: None,
+    };
+
+    let _ignore_lack_of_recv = send_new_connection.send(new_connection);
+
+    let mut keepalive = idle.keepalive_interval();
+    let mut lines = Rate::new();
+
+    loop {
+        tokio::select! {
+            message = websocket.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let received = if json { receive_json(&text) } else { receive_text(&text) };
+                    let mut exceeded = None;
+
+                    for received in received {
+                        match received {
+                            Received::Input(input) => {
+                                if input.len() > limits.line_length {
+                                    exceeded = Some(LimitExceeded::LineLength);
+                                    break;
+                                }
+
+                                if lines.exceeded(limits.lines_per_second) {
+                                    exceeded = Some(LimitExceeded::LineRate);
+                                    break;
+                                }
+
+                                last_input.touch();
+                                let _ignore_lack_of_recv = send_input.send(input);
+                            },
+                            Received::Event(event) => {
+                                let _ignore_lack_of_recv = send_event.send(event);
+                            },
+                        }
+                    }
+
+                    if let Some(exceeded) = exceeded {
+                        eprintln!("Closing websocket connection from {}: {}", addr, exceeded);
+
+                        let output = TelnetFrame::Data(format!("{}\r\n", exceeded).into());
+                        if let Some(message) = if json { send_json(output) } else { send_text(output) } {
+                            let _ = websocket.send(Message::Text(message)).await;
+                        }
+
+                        break;
+                    }
+                },
+                Some(Ok(Message::Close(_))) | None => break,
+                // Pings are answered by tungstenite. Binary frames have no meaning here.
+                Some(Ok(_)) => {},
+                Some(Err(err)) => {
+                    eprintln!("Closing websocket connection from {}: {}", addr, err);
+                    break;
+                },
+            },
+
+            output = recv_output.recv() => match output {
+                Some(output) => {
+                    let message = if json { send_json(output) } else { send_text(output) };
+
+                    if let Some(message) = message {
+                        tokio::select! {
+                            result = websocket.send(Message::Text(message)) => if result.is_err() {
+                                break;
+                            },
+                            _ = recv_output.overflowed() => {
+                                eprintln!("Closing websocket connection from {}: it is not reading its output", addr);
+                                break;
+                            },
+                        }
+                    }
+                },
+                None => break,
+            },
+
+            // Closed by the exec instead, so that the player keeps playing if
+            // it fails.
+            Ok(Detach(send_detached)) = detach.recv() => {
+                let _ignore_lack_of_recv = send_detached.send(None);
+
+                let notice = TelnetFrame::Data(copyover::RESTARTING_WITHOUT_HANDOVER.into());
+
+                if let Some(message) = if json { send_json(notice) } else { send_text(notice) } {
+                    match tokio::time::timeout(COPYOVER_NOTICE_TIMEOUT, websocket.send(Message::Text(message))).await {
+                        Ok(Ok(())) => {},
+                        _ => break,
+                    }
+                }
+            },
+
+            // Writing to a half-open connection eventually fails, ending it.
+            // A client that stopped reading leaves the write waiting instead.
+            _ = keepalive.tick(), if idle.keepalive > 0 => {
+                match tokio::time::timeout(idle.keepalive_period(), websocket.send(Message::Ping(vec![]))).await {
+                    Ok(Ok(())) => {},
+                    Ok(Err(_)) => break,
+                    Err(_) => {
+                        eprintln!("Closing websocket connection from {}: it is not reading its keepalives", addr);
+                        break;
+                    },
+                }
+            },
+        }
+    }
+
+    let _ = websocket.close(None).await;
+    let _ignore_lack_of_recv = send_disconnect.send(Disconnected);
+}
+
+enum Received {
+    Input(Input),
+    Event(Event),
+}
+
+fn receive_text(text: &str) -> Vec<Received> {
+    text.lines().map(|line| Received::Input(line.to_string())).collect()
+}
+
+fn receive_json(text: &str) -> Vec<Received> {
+    let message = match serde_json::from_str::<Value>(text) {
+        Ok(message) => message,
+        Err(err) => {
+            eprintln!("Ignoring invalid websocket JSON message: {}", err);
+            return vec![];
+        },
+    };
+
+    if let Some(text) = message["text"].as_str() {
+        return receive_text(text);
+    }
+
+    if let Some(package) = message["gmcp"].as_str() {
+        let data = match &message["data"] {
+            Value::Null => String::new(),
+            data => data.to_string(),
+        };
+
+        return vec![Received::Event(Event::Gmcp { package: package.to_string(), data })];
+    }
+
+    vec![]
+}
+
+fn send_text(output: Output) -> Option<String> {
+    match output {
+        TelnetFrame::Data(data) | TelnetFrame::Prompt(data) => Some(String::from_utf8_lossy(&data).into_owned()),
+        // Everything else is TELNET specific.
+        _ => None,
+    }
+}
+
+fn send_json(output: Output) -> Option<String> {
+    let message = match output {
+        TelnetFrame::Data(data) => json!({ "text": String::from_utf8_lossy(&data) }),
+        TelnetFrame::Prompt(data) => json!({ "text": String::from_utf8_lossy(&data), "prompt": true }),
+
+        TelnetFrame::Gmcp { package, data } => {
+            match serde_json::from_str::<Value>(&data) {
+                Ok(data) => json!({ "gmcp": package, "data": data }),
+                Err(_) => json!({ "gmcp": package }),
+            }
+        },
+
+        TelnetFrame::Negotiate(Verb::Will, TelnetOption::Echo) => json!({ "echo": false }),
+        TelnetFrame::Negotiate(Verb::Wont, TelnetOption::Echo) => json!({ "echo": true }),
+
+        // Everything else is TELNET specific.
+        _ => return None,
+    };
+
+    Some(message.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_messages() {
+        assert_eq!(send_json(TelnetFrame::Prompt("\r\n> ".into())), Some(r#"{"prompt":true,"text":"\r\n> "}"#.to_string()));
+        assert_eq!(send_json(TelnetFrame::Gmcp { package: "Room.Info".into(), data: r#"{"num":1}"#.into() }), Some(r#"{"data":{"num":1},"gmcp":"Room.Info"}"#.to_string()));
+        assert_eq!(send_json(TelnetFrame::Negotiate(Verb::Will, TelnetOption::Echo)), Some(r#"{"echo":false}"#.to_string()));
+
+        match &receive_json(r#"{"gmcp": "Core.Hello", "data": {"client": "web"}}"#)[..] {
+            [Received::Event(Event::Gmcp { package, data })] => {
+                assert_eq!(package, "Core.Hello");
+                assert_eq!(data, r#"{"client":"web"}"#);
+            },
+            _ => panic!("Expected one GMCP event"),
+        }
+    }
+}
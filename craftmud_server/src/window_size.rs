@@ -0,0 +1,34 @@
+//! The size of the player's terminal window.
+
+use serde::{Deserialize, Serialize};
+
+/// Reported by the client via NAWS. Clients that do not report a size are
+/// assumed to be the traditional 80x24.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowSize {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl WindowSize {
+    /// Update from a NAWS report, keeping the current value of any
+    /// dimension the client says it does not know.
+    pub fn update(&mut self, width: u16, height: u16) {
+        if width != 0 {
+            self.width = width;
+        }
+
+        if height != 0 {
+            self.height = height;
+        }
+    }
+}
+
+impl Default for WindowSize {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            height: 24,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Playing through login and the tutorial on a running server.
+//!
+//! The server needs its database, so this is ignored unless run with
+//! `cargo test -- --ignored` where the database in `src/db_config.rs` is up.
+
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use telnet_server::client::Script;
+use telnet_server::TelnetClient;
+
+/// How long the server gets to start listening.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Kills the server when the test ends, passing or not.
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Start the server listening for plain TELNET on a free port.
+fn start_server() -> (Server, String) {
+    let address = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+
+    let config = std::env::temp_dir().join(format!("craftmud-test-{}.toml", std::process::id()));
+    std::fs::write(&config, format!("[[listen]]\nprotocol = \"plain\"\naddress = \"{}\"\n", address)).unwrap();
+
+    let child = Command::new(env!("CARGO_BIN_EXE_server"))
+    .env("CRAFTMUD_CONFIG", &config)
+    .stdout(Stdio::null())
+    .spawn()
+    .expect("Unable to start the server");
+
+    (Server(child), address)
+}
+
+async fn connect(address: &str) -> TelnetClient {
+    let started = Instant::now();
+
+    loop {
+        match TelnetClient::connect(address).await {
+            Ok(client) => return client,
+            Err(err) if started.elapsed() > STARTUP_TIMEOUT => panic!("Unable to connect to the server: {}", err),
+            Err(_) => tokio::time::delay_for(Duration::from_millis(100)).await,
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn tutorial() {
+    let (_server, address) = start_server();
+    let mut client = connect(&address).await;
+
+    let script = Script::parse(include_str!("scripts/tutorial.txt")).unwrap();
+    script.run(&mut client).await.unwrap();
+}
@@ -1,56 +1,46 @@
-use tokio;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpListener;
+//! Run a script against a TELNET server, printing the session as it goes.
+//!
+//! ```text
+//! play <address> <script>
+//! ```
+//!
+//! Exits with 1 once a step fails, such as when expected text isn't received
+//! in time. See `telnet_server::client` for the script format.
 
-use std::env;
-use std::error::Error;
+use telnet_server::client::{Script, Step, TelnetClient};
+
+const USAGE: &str = "Usage: play <address> <script>";
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    // Allow passing an address to listen on as the first argument of this
-    // program, but otherwise we'll just set up our TCP listener on
-    // 127.0.0.1:8080 for connections.
-    let addr = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
-
-    // Next up we create a TCP listener which will listen for incoming
-    // connections. This TCP listener is bound to the address we determined
-    // above and must be associated with an event loop.
-    let mut listener = TcpListener::bind(&addr).await?;
-    println!("Listening on: {}", addr);
-
-    loop {
-        // Asynchronously wait for an inbound socket.
-        let (mut socket, _) = listener.accept().await?;
-
-        // And this is where much of the magic of this server happens. We
-        // crucially want all clients to make progress concurrently, rather than
-        // blocking one on completion of another. To achieve this we use the
-        // `tokio::spawn` function to execute the work in the background.
-        //
-        // Essentially here we're executing a new task to run concurrently,
-        // which will allow all of our clients to be processed concurrently.
-
-        tokio::spawn(async move {
-            let mut buf = [0; 1024];
-
-            // In a loop, read data from the socket and write the data back.
-            loop {
-                let n = socket
-                    .read(&mut buf)
-                    .await
-                    .expect("failed to read data from socket");
-
-                if n == 0 {
-                    return;
-                }
-
-                socket
-                    .write_all(&buf[0..n])
-                    .await
-                    .expect("failed to write data to socket");
-            }
-        });
+async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let result = match &args[..] {
+        [address, path] => play(address, path).await,
+        _ => Err(USAGE.to_string()),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
     }
-}
\ No newline at end of file
+}
+
+async fn play(address: &str, path: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|err| format!("Unable to read script {}: {}", path, err))?;
+    let script = Script::parse(&text).map_err(|err| format!("Invalid script {}: {}", path, err))?;
+
+    let mut client = TelnetClient::connect(address).await.map_err(|err| format!("Unable to connect to {}: {}", address, err))?;
+
+    for (number, step) in script.steps() {
+        if let Step::Send(line) = step {
+            println!("> {}", line);
+        }
+
+        let received = client.run(step).await.map_err(|err| format!("{}:{}: {}", path, number, err))?;
+        print!("{}", received);
+    }
+
+    println!();
+    Ok(())
+}
@@ -0,0 +1,188 @@
+//! Character set negotiation.
+//!
+//! https://tools.ietf.org/html/rfc2066
+//!
+//! The server offers UTF-8 first, falling back to Latin-1 or CP437 for old
+//! clients. Until a character set is agreed on, UTF-8 is assumed.
+
+use std::convert::TryFrom;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+const REQUEST: u8 = 1;
+const ACCEPTED: u8 = 2;
+const REJECTED: u8 = 3;
+
+/// Character sets this server can use, in order of preference.
+const SUPPORTED: [Charset; 3] = [Charset::Utf8, Charset::Latin1, Charset::Cp437];
+
+/// Characters for the bytes 0x80 to 0xFF in CP437.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/// A character set for user data.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub enum Charset {
+    #[default]
+    Utf8,
+    Latin1,
+    Cp437,
+}
+
+impl Charset {
+    /// The name registered with IANA.
+    pub fn name(self) -> &'static str {
+        match self {
+            Charset::Utf8 => "UTF-8",
+            Charset::Latin1 => "ISO-8859-1",
+            Charset::Cp437 => "IBM437",
+        }
+    }
+
+    /// Look up a character set by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match &*name.trim().to_uppercase() {
+            "UTF-8" | "UTF8" => Some(Charset::Utf8),
+            "ISO-8859-1" | "ISO_8859-1" | "LATIN1" | "LATIN-1" => Some(Charset::Latin1),
+            "IBM437" | "CP437" => Some(Charset::Cp437),
+            _ => None,
+        }
+    }
+
+    /// Decode user data. Invalid UTF-8 is replaced with U+FFFD.
+    pub(crate) fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Charset::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Charset::Latin1 => bytes.iter().map(|&byte| byte as char).collect(),
+            Charset::Cp437 => bytes.iter().map(|&byte| match byte {
+                0..=0x7f => byte as char,
+                _ => CP437_HIGH[byte as usize - 0x80],
+            }).collect(),
+        }
+    }
+
+    /// Encode UTF-8 text. Characters the charset lacks become `?`.
+    pub(crate) fn encode(self, text: &str) -> Bytes {
+        if self == Charset::Utf8 {
+            return Bytes::copy_from_slice(text.as_bytes());
+        }
+
+        text.chars()
+        .map(|ch| self.encode_char(ch).unwrap_or(b'?'))
+        .collect::<Vec<u8>>()
+        .into()
+    }
+
+    fn encode_char(self, ch: char) -> Option<u8> {
+        match self {
+            _ if ch.is_ascii() => Some(ch as u8),
+            Charset::Utf8 => None,
+            Charset::Latin1 => u8::try_from(ch as u32).ok(),
+            Charset::Cp437 => CP437_HIGH.iter().position(|&high| high == ch).map(|index| (index + 0x80) as u8),
+        }
+    }
+}
+
+/// A CHARSET subnegotiation from the client.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Message {
+    /// The client offers character sets, in order of preference.
+    Request(Vec<String>),
+    Accepted(String),
+    Rejected,
+}
+
+pub(crate) fn parse(payload: &[u8]) -> Option<Message> {
+    let (&command, rest) = payload.split_first()?;
+
+    match command {
+        REQUEST => {
+            // Translation tables are not supported, so the version is skipped.
+            let rest = match rest.strip_prefix(b"[TTABLE]") {
+                Some(rest) => rest.get(1..)?,
+                None => rest,
+            };
+
+            let (&separator, names) = rest.split_first()?;
+            let names = names.split(|&byte| byte == separator)
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .collect();
+
+            Some(Message::Request(names))
+        },
+
+        ACCEPTED => Some(Message::Accepted(String::from_utf8_lossy(rest).into_owned())),
+        REJECTED => Some(Message::Rejected),
+        _ => None,
+    }
+}
+
+/// The payload offering every supported charset.
+pub(crate) fn request() -> Bytes {
+    let mut payload = BytesMut::new();
+    payload.put_u8(REQUEST);
+
+    for charset in SUPPORTED.iter() {
+        payload.put_u8(b';');
+        payload.extend_from_slice(charset.name().as_bytes());
+    }
+
+    payload.freeze()
+}
+
+/// The answer to a client's request. Picks the first offered charset this
+/// server supports.
+pub(crate) fn answer(offered: &[String]) -> (Bytes, Option<Charset>) {
+    let mut payload = BytesMut::new();
+
+    match offered.iter().find_map(|name| Some((name, Charset::from_name(name)?))) {
+        Some((name, charset)) => {
+            payload.put_u8(ACCEPTED);
+            payload.extend_from_slice(name.as_bytes());
+            (payload.freeze(), Some(charset))
+        },
+
+        None => {
+            payload.put_u8(REJECTED);
+            (payload.freeze(), None)
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_request() {
+        assert_eq!(parse(b"\x01;UTF-8;ISO-8859-1"), Some(Message::Request(vec!["UTF-8".into(), "ISO-8859-1".into()])));
+        assert_eq!(parse(b"\x01[TTABLE]\x01 ASCII"), Some(Message::Request(vec!["ASCII".into()])));
+        assert_eq!(parse(b"\x02UTF-8"), Some(Message::Accepted("UTF-8".into())));
+    }
+
+    #[test]
+    fn answer_picks_first_supported() {
+        let (payload, charset) = answer(&["KOI8-R".into(), "CP437".into(), "UTF-8".into()]);
+
+        assert_eq!(&payload[..], b"\x02CP437");
+        assert_eq!(charset, Some(Charset::Cp437));
+    }
+
+    #[test]
+    fn decode_and_encode() {
+        assert_eq!(Charset::Utf8.decode(b"caf\xc3\xa9 \xff"), "caf\u{e9} \u{fffd}");
+        assert_eq!(Charset::Latin1.decode(b"caf\xe9"), "caf\u{e9}");
+        assert_eq!(Charset::Cp437.decode(b"caf\x82 \xb0"), "caf\u{e9} \u{2591}");
+
+        assert_eq!(&Charset::Latin1.encode("caf\u{e9} \u{2591}")[..], b"caf\xe9 ?");
+        assert_eq!(&Charset::Cp437.encode("caf\u{e9} \u{2591}")[..], b"caf\x82 \xb0");
+    }
+}
@@ -0,0 +1,256 @@
+//! A TELNET client for driving a server from a script, such as in tests.
+//!
+//! `TelnetClient` connects, answers option negotiation, sends lines, and
+//! waits for the server to send expected text. A `Script` is a list of those
+//! steps read from text:
+//!
+//! ```text
+//! # Comments and blank lines are skipped.
+//! timeout 10
+//! < Welcome
+//! > tutorial
+//! < Starting tutorial.
+//! > quit
+//! closed
+//! ```
+//!
+//! `> text` sends a line, `< text` waits for the server to send the text,
+//! `timeout <seconds>` sets how long later steps wait, and `closed` waits for
+//! the server to close the connection.
+
+use std::time::Duration;
+
+use futures::{SinkExt as _, StreamExt as _};
+use tokio::io::{Error as TokioIoError, ErrorKind};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::time::Instant;
+use tokio_util::codec::Framed;
+
+use crate::codec::{TelnetCodec, TelnetEvent, TelnetFrame};
+use crate::control_codes::ControlCode;
+use crate::negotiation::Negotiator;
+use crate::options::TelnetOption;
+
+/// How long steps wait for the server unless the timeout is changed.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The client side of a TELNET connection.
+///
+/// The server may echo and may mark prompts with END OF RECORD. Every other
+/// option is refused.
+pub struct TelnetClient {
+    framed: Framed<TcpStream, TelnetCodec>,
+    negotiator: Negotiator,
+    /// Text received that `expect` has not gone past yet. Lines end in `\n`.
+    received: String,
+    timeout: Duration,
+}
+
+impl TelnetClient {
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, TokioIoError> {
+        let tcp = TcpStream::connect(addr).await?;
+
+        let mut codec = TelnetCodec::new();
+        // The line length limit is for input typed by players, not for what
+        // servers send.
+        codec.set_max_line_length(usize::MAX);
+
+        let mut negotiator = Negotiator::new();
+        negotiator.support_remote(TelnetOption::Echo);
+        negotiator.support_remote(TelnetOption::EndOfRecord);
+        negotiator.support_remote(TelnetOption::SuppressGoAhead);
+
+        Ok(Self { framed: Framed::new(tcp, codec), negotiator, received: String::new(), timeout: DEFAULT_TIMEOUT })
+    }
+
+    /// Set how long `expect` and `expect_closed` wait for the server.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Options are local when this client performs them and remote when the
+    /// server does.
+    pub fn negotiator(&self) -> &Negotiator {
+        &self.negotiator
+    }
+
+    pub async fn send_line(&mut self, line: &str) -> Result<(), TokioIoError> {
+        self.framed.send(TelnetFrame::Data(format!("{}\r\n", line).into())).await
+    }
+
+    /// Wait for the server to send `expected`, returning what it sent up to
+    /// and including it. The next `expect` only looks at what comes after.
+    ///
+    /// Prompts are only seen once the server ends them with GA or EOR.
+    pub async fn expect(&mut self, expected: &str) -> Result<String, TokioIoError> {
+        let deadline = Instant::now() + self.timeout;
+
+        loop {
+            if let Some(index) = self.received.find(expected) {
+                let rest = self.received.split_off(index + expected.len());
+                return Ok(std::mem::replace(&mut self.received, rest));
+            }
+
+            match tokio::time::timeout_at(deadline, self.framed.next()).await {
+                Ok(Some(event)) => self.receive(event?).await?,
+                Ok(None) => return Err(self.failure(ErrorKind::UnexpectedEof, &format!("connection closed before {:?} was received", expected))),
+                Err(_) => return Err(self.failure(ErrorKind::TimedOut, &format!("timed out waiting for {:?}", expected))),
+            }
+        }
+    }
+
+    /// Wait for the server to close the connection, returning what it sent
+    /// that `expect` has not gone past.
+    pub async fn expect_closed(&mut self) -> Result<String, TokioIoError> {
+        let deadline = Instant::now() + self.timeout;
+
+        loop {
+            match tokio::time::timeout_at(deadline, self.framed.next()).await {
+                Ok(Some(event)) => self.receive(event?).await?,
+                Ok(None) => return Ok(std::mem::take(&mut self.received)),
+                Err(_) => return Err(self.failure(ErrorKind::TimedOut, "timed out waiting for the connection to close")),
+            }
+        }
+    }
+
+    /// Run one step of a script, returning what the server sent for it.
+    pub async fn run(&mut self, step: &Step) -> Result<String, TokioIoError> {
+        match step {
+            Step::Send(line) => self.send_line(line).await.map(|()| String::new()),
+            Step::Expect(text) => self.expect(text).await,
+            Step::Timeout(timeout) => {
+                self.set_timeout(*timeout);
+                Ok(String::new())
+            },
+            Step::Closed => self.expect_closed().await,
+        }
+    }
+
+    async fn receive(&mut self, event: TelnetEvent) -> Result<(), TokioIoError> {
+        match event {
+            TelnetEvent::Line(line) => {
+                self.received.push_str(&line);
+                self.received.push('\n');
+            },
+
+            TelnetEvent::Command(ControlCode::GoAhead) | TelnetEvent::Command(ControlCode::EOR) => {
+                if let Some(prompt) = self.framed.codec_mut().take_partial_line() {
+                    self.received.push_str(&prompt);
+                }
+            },
+
+            TelnetEvent::Negotiate(verb, option) => {
+                if let Some((verb, option)) = self.negotiator.receive(verb, option).reply {
+                    self.framed.send(TelnetFrame::Negotiate(verb, option)).await?;
+                }
+            },
+
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    /// An error saying what went wrong along with what was received.
+    fn failure(&self, kind: ErrorKind, message: &str) -> TokioIoError {
+        TokioIoError::new(kind, format!("{}; received {:?}", message, self.received))
+    }
+}
+
+/// Something for a `TelnetClient` to do.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Step {
+    /// `> text`
+    Send(String),
+
+    /// `< text`
+    Expect(String),
+
+    /// `timeout <seconds>`
+    Timeout(Duration),
+
+    /// `closed`
+    Closed,
+}
+
+/// Steps along with the line of the script they are on.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Script {
+    steps: Vec<(usize, Step)>,
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Result<Self, TokioIoError> {
+        let mut steps = vec![];
+
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let invalid = |message: &str| TokioIoError::new(ErrorKind::InvalidData, format!("line {}: {}", number, message));
+
+            let step = if let Some(text) = line.strip_prefix('>') {
+                Step::Send(strip_space(text).to_string())
+            } else if let Some(text) = line.strip_prefix('<') {
+                match strip_space(text) {
+                    "" => return Err(invalid("nothing to expect")),
+                    text => Step::Expect(text.to_string()),
+                }
+            } else {
+                match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [] => continue,
+                    [comment, ..] if comment.starts_with('#') => continue,
+                    ["timeout", seconds] => match seconds.parse::<f64>() {
+                        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => Step::Timeout(Duration::from_secs_f64(seconds)),
+                        _ => return Err(invalid("the timeout must be a positive number of seconds")),
+                    },
+                    ["closed"] => Step::Closed,
+                    _ => return Err(invalid("expected `> text`, `< text`, `timeout <seconds>`, or `closed`")),
+                }
+            };
+
+            steps.push((number, step));
+        }
+
+        Ok(Self { steps })
+    }
+
+    pub fn steps(&self) -> &[(usize, Step)] {
+        &self.steps
+    }
+
+    /// Run every step, stopping at the first to fail.
+    pub async fn run(&self, client: &mut TelnetClient) -> Result<(), TokioIoError> {
+        for (number, step) in &self.steps {
+            if let Err(err) = client.run(step).await {
+                return Err(TokioIoError::new(err.kind(), format!("line {}: {}", number, err)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Remove the space separating `>` or `<` from the text.
+fn strip_space(text: &str) -> &str {
+    text.strip_prefix(' ').unwrap_or(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scripts_parse() {
+        let script = Script::parse("# Log in.\n\ntimeout 2.5\n< Name: \n> Havvy\n>\nclosed\n").unwrap();
+
+        assert_eq!(script.steps(), &[
+            (3, Step::Timeout(Duration::from_millis(2500))),
+            (4, Step::Expect("Name: ".into())),
+            (5, Step::Send("Havvy".into())),
+            (6, Step::Send("".into())),
+            (7, Step::Closed),
+        ]);
+
+        let err = Script::parse("> look\nlook\n").unwrap_err();
+        assert!(err.to_string().starts_with("line 2: "));
+    }
+}
@@ -0,0 +1,765 @@
+//! Splitting the byte stream into user data and TELNET commands.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio::io::Error as TokioIoError;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::charset::Charset;
+use crate::control_codes::ControlCode;
+use crate::gmcp;
+use crate::limits::{LimitExceeded, Limits};
+use crate::mccp::{Compressor, Decompressor};
+use crate::msdp::{self, MsdpValue};
+use crate::negotiation::{OptionChange, Verb};
+use crate::options::TelnetOption;
+use crate::ttype::TerminalType;
+
+const IAC: u8 = ControlCode::IAC as u8;
+
+/// The most inflated MCCP3 input waiting to be parsed, so that a small
+/// compressed payload can't fill memory before lines are checked.
+const MAX_INFLATED: usize = 64 * 1024;
+
+/// Something received from the client.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TelnetEvent {
+    /// A line of user data without the line ending. Bytes that are invalid
+    /// in the connection's character set are replaced with U+FFFD.
+    Line(String),
+
+    /// A command that is not part of option negotiation such as AYT.
+    ///
+    /// EC and EL are applied to the line being received instead.
+    Command(ControlCode),
+
+    /// WILL, WONT, DO, or DONT for an option.
+    ///
+    /// Only produced by `TelnetCodec`. `TelnetStream` answers these itself
+    /// and reports `OptionChange` instead.
+    Negotiate(Verb, TelnetOption),
+
+    /// IAC SB <option> <payload> IAC SE
+    Subnegotiation(TelnetOption, Bytes),
+
+    /// An option was enabled or disabled by negotiation.
+    OptionChange(OptionChange),
+
+    /// The client's window size, from NAWS. A dimension of 0 means unknown.
+    WindowSize { width: u16, height: u16 },
+
+    /// Every terminal type the client reported, including MTTS capabilities.
+    TerminalType(TerminalType),
+
+    /// The character set agreed on with CHARSET.
+    Charset(Charset),
+
+    /// A GMCP message. `data` is JSON, or empty if the message had none.
+    Gmcp { package: String, data: String },
+
+    /// An MSDP variable set by the client. MSDP commands such as REPORT are
+    /// answered by `TelnetStream` and not reported.
+    Msdp { variable: String, value: MsdpValue },
+}
+
+/// Something to send to the client.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TelnetFrame {
+    /// User data. It is UTF-8 that gets converted to the connection's
+    /// character set. IAC bytes are escaped.
+    Data(Bytes),
+
+    /// User data ending in a prompt. It is followed by IAC EOR once END OF
+    /// RECORD is enabled and IAC GA otherwise, so clients can find prompts.
+    Prompt(Bytes),
+
+    /// A command that is not part of option negotiation such as GA or NOP.
+    Command(ControlCode),
+
+    /// WILL, WONT, DO, or DONT for an option.
+    ///
+    /// When sent through a `TelnetStream`, this is a request to change the
+    /// option and is only sent if the Q Method allows it.
+    Negotiate(Verb, TelnetOption),
+
+    /// IAC SB <option> <payload> IAC SE. IAC bytes in the payload are escaped.
+    ///
+    /// Sending IAC SB MCCP2 IAC SE starts compressing everything after it and
+    /// sending IAC WONT MCCP2 ends the compression first.
+    Subnegotiation(TelnetOption, Bytes),
+
+    /// A GMCP message. `data` is JSON, or empty to send no data.
+    ///
+    /// When sent through a `TelnetStream`, this is dropped unless the client
+    /// has enabled GMCP.
+    Gmcp { package: String, data: String },
+
+    /// The value of an MSDP variable.
+    ///
+    /// When sent through a `TelnetStream`, this publishes the variable and
+    /// it is only sent when the client has asked for it to be reported and
+    /// the value changed.
+    Msdp { variable: String, value: MsdpValue },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ParseState {
+    Data,
+    /// Just saw a CR in user data.
+    Cr,
+    /// Just saw an IAC.
+    Iac,
+    /// Just saw IAC followed by WILL, WONT, DO, or DONT.
+    Negotiate(Verb),
+    /// Just saw IAC SB.
+    SubnegotiationOption,
+    /// Inside of a subnegotiation.
+    Subnegotiation(TelnetOption),
+    /// Just saw an IAC inside of a subnegotiation.
+    SubnegotiationIac(TelnetOption),
+}
+
+/// Decodes bytes into `TelnetEvent`s and encodes `TelnetFrame`s into bytes.
+///
+/// This does no option negotiation. Use `TelnetStream` for that.
+pub struct TelnetCodec {
+    state: ParseState,
+    line: Vec<u8>,
+    subnegotiation: BytesMut,
+    /// Received bytes that are ready to be parsed.
+    plain: BytesMut,
+    /// Set while MCCP2 is compressing outgoing data.
+    compressor: Option<Compressor>,
+    /// Whether the client may start MCCP3.
+    input_compression_allowed: bool,
+    /// Set while MCCP3 is compressing incoming data.
+    decompressor: Option<Decompressor>,
+    /// Whether prompts end with EOR instead of GA.
+    end_of_record: bool,
+    /// The character set of user data in both directions.
+    charset: Charset,
+    /// The most bytes in a line or subnegotiation.
+    max_line_length: usize,
+}
+
+impl TelnetCodec {
+    pub fn new() -> Self {
+        Self {
+            state: ParseState::Data,
+            line: Vec::with_capacity(128),
+            subnegotiation: BytesMut::new(),
+            plain: BytesMut::new(),
+            compressor: None,
+            input_compression_allowed: false,
+            decompressor: None,
+            end_of_record: false,
+            charset: Charset::Utf8,
+            max_line_length: Limits::default().line_length,
+        }
+    }
+
+    /// Allow the client to start compressing its data with MCCP3. Set once
+    /// MCCP3 has been negotiated.
+    pub fn set_input_compression_allowed(&mut self, allowed: bool) {
+        self.input_compression_allowed = allowed;
+    }
+
+    /// End prompts with EOR instead of GA. Set once END OF RECORD has been
+    /// negotiated.
+    pub fn set_end_of_record(&mut self, end_of_record: bool) {
+        self.end_of_record = end_of_record;
+    }
+
+    /// Set the character set for user data. Set once CHARSET has agreed on one.
+    pub fn set_charset(&mut self, charset: Charset) {
+        self.charset = charset;
+    }
+
+    pub fn charset(&self) -> Charset {
+        self.charset
+    }
+
+    /// Set the most bytes in a line or subnegotiation. Longer input is an
+    /// error.
+    pub fn set_max_line_length(&mut self, max_line_length: usize) {
+        self.max_line_length = max_line_length;
+    }
+
+    /// Take the user data received since the last line ended. Clients call
+    /// this on GA or EOR to read the prompt before it.
+    pub fn take_partial_line(&mut self) -> Option<String> {
+        match self.take_line() {
+            TelnetEvent::Line(line) if !line.is_empty() => Some(line),
+            _ => None,
+        }
+    }
+
+    /// Move received bytes into `plain`, inflating them while MCCP3 is active.
+    /// Compressed bytes that would inflate past `MAX_INFLATED` are left in
+    /// `incoming`.
+    fn receive(&mut self, incoming: &mut BytesMut) -> Result<(), TokioIoError> {
+        if let Some(ref mut decompressor) = self.decompressor {
+            let room = MAX_INFLATED.saturating_sub(self.plain.len());
+
+            if !decompressor.decompress(incoming, &mut self.plain, room)? {
+                return Ok(());
+            }
+
+            // The client ended compression. Whatever is left is uncompressed.
+            self.decompressor = None;
+        }
+
+        self.plain.extend_from_slice(incoming);
+        incoming.clear();
+        Ok(())
+    }
+
+    /// Take the input received but not yet made into events, such as a partly
+    /// typed line, so that another codec can parse it. `src` is what was
+    /// read but not decoded. A command cut off partway is lost, and so is
+    /// compressed input that would inflate past `MAX_INFLATED`.
+    pub fn take_input(&mut self, mut src: BytesMut) -> Result<Vec<u8>, TokioIoError> {
+        // Line data had IAC doubled when it was received.
+        let mut input = Vec::with_capacity(self.line.len() + self.plain.len() + src.len());
+        for &byte in &std::mem::take(&mut self.line) {
+            input.push(byte);
+            if byte == IAC {
+                input.push(IAC);
+            }
+        }
+
+        if let ParseState::Cr = self.state {
+            input.push(b'\r');
+        }
+        self.state = ParseState::Data;
+
+        self.receive(&mut src)?;
+        input.extend_from_slice(&self.plain.split());
+        Ok(input)
+    }
+
+    /// Whether outgoing data is being compressed with MCCP2.
+    pub fn is_compressing(&self) -> bool {
+        self.compressor.is_some()
+    }
+
+    fn push_line(&mut self, byte: u8) -> Result<(), TokioIoError> {
+        if self.line.len() >= self.max_line_length {
+            return Err(LimitExceeded::LineLength.into());
+        }
+
+        self.line.push(byte);
+        Ok(())
+    }
+
+    fn push_subnegotiation(&mut self, byte: u8) -> Result<(), TokioIoError> {
+        if self.subnegotiation.len() >= self.max_line_length {
+            return Err(LimitExceeded::LineLength.into());
+        }
+
+        self.subnegotiation.put_u8(byte);
+        Ok(())
+    }
+
+    fn take_line(&mut self) -> TelnetEvent {
+        let line = std::mem::replace(&mut self.line, Vec::with_capacity(128));
+        TelnetEvent::Line(self.charset.decode(&line))
+    }
+
+    /// Apply Erase Character by removing the last character of the line.
+    fn erase_character(&mut self) {
+        // In UTF-8, a character is its lead byte plus any continuation bytes.
+        if self.charset == Charset::Utf8 {
+            while let Some(&byte) = self.line.last() {
+                self.line.pop();
+
+                if byte & 0b1100_0000 != 0b1000_0000 {
+                    break;
+                }
+            }
+        } else {
+            self.line.pop();
+        }
+    }
+
+    /// Feed a single byte to the parser, returning an event if the byte completed one.
+    fn parse(&mut self, byte: u8) -> Result<Option<TelnetEvent>, TokioIoError> {
+        let (next_state, event) = match self.state {
+            ParseState::Data if byte == IAC => (ParseState::Iac, None),
+
+            ParseState::Data => match byte {
+                b'\r' => (ParseState::Cr, None),
+                b'\n' => (ParseState::Data, Some(self.take_line())),
+                byte => {
+                    self.push_line(byte)?;
+                    (ParseState::Data, None)
+                },
+            },
+
+            // CR LF and CR NUL both end the line. A bare CR ends the line too
+            // since some clients only send that. Repeated CRs, as in CR CR LF,
+            // are a single line end.
+            ParseState::Cr => match byte {
+                b'\n' | b'\0' => (ParseState::Data, Some(self.take_line())),
+                b'\r' => (ParseState::Cr, None),
+                IAC => (ParseState::Iac, Some(self.take_line())),
+                byte => {
+                    let event = self.take_line();
+                    self.push_line(byte)?;
+                    (ParseState::Data, Some(event))
+                },
+            },
+
+            ParseState::Iac => match ControlCode::from_u8(byte) {
+                Some(ControlCode::IAC) => {
+                    self.push_line(IAC)?;
+                    (ParseState::Data, None)
+                },
+                Some(ControlCode::WILL) => (ParseState::Negotiate(Verb::Will), None),
+                Some(ControlCode::WONT) => (ParseState::Negotiate(Verb::Wont), None),
+                Some(ControlCode::DO) => (ParseState::Negotiate(Verb::Do), None),
+                Some(ControlCode::DONT) => (ParseState::Negotiate(Verb::Dont), None),
+                Some(ControlCode::SB) => (ParseState::SubnegotiationOption, None),
+                Some(ControlCode::EraseCharacter) => {
+                    self.erase_character();
+                    (ParseState::Data, None)
+                },
+                Some(ControlCode::EraseLine) => {
+                    self.line.clear();
+                    (ParseState::Data, None)
+                },
+                // A stray SE outside of a subnegotiation is meaningless.
+                Some(ControlCode::SE) => (ParseState::Data, None),
+                Some(command) => (ParseState::Data, Some(TelnetEvent::Command(command))),
+                // Not a command; drop it.
+                None => (ParseState::Data, None),
+            },
+
+            ParseState::Negotiate(verb) => (ParseState::Data, Some(TelnetEvent::Negotiate(verb, byte.into()))),
+
+            ParseState::SubnegotiationOption => {
+                self.subnegotiation.clear();
+                (ParseState::Subnegotiation(byte.into()), None)
+            },
+
+            ParseState::Subnegotiation(option) => {
+                if byte == IAC {
+                    (ParseState::SubnegotiationIac(option), None)
+                } else {
+                    self.push_subnegotiation(byte)?;
+                    (ParseState::Subnegotiation(option), None)
+                }
+            },
+
+            ParseState::SubnegotiationIac(option) => match ControlCode::from_u8(byte) {
+                Some(ControlCode::IAC) => {
+                    self.push_subnegotiation(IAC)?;
+                    (ParseState::Subnegotiation(option), None)
+                },
+                Some(ControlCode::SE) => {
+                    let payload = self.subnegotiation.split().freeze();
+                    (ParseState::Data, Some(TelnetEvent::Subnegotiation(option, payload)))
+                },
+                // Anything else is a protocol violation. Keep whatever we have.
+                _ => (ParseState::Subnegotiation(option), None),
+            },
+        };
+
+        self.state = next_state;
+        Ok(event)
+    }
+}
+
+impl Default for TelnetCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for TelnetCodec {
+    type Item = TelnetEvent;
+    type Error = TokioIoError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            self.receive(src)?;
+
+            let mut consumed = 0;
+
+            while consumed < self.plain.len() {
+                let byte = self.plain[consumed];
+                consumed += 1;
+
+                if let Some(event) = self.parse(byte)? {
+                    self.plain.advance(consumed);
+
+                    // IAC SB MCCP3 IAC SE means everything after it is
+                    // compressed. It is inflated by the next call.
+                    let starts_decompression = matches!(event, TelnetEvent::Subnegotiation(TelnetOption::Mccp3, _));
+
+                    if starts_decompression && self.input_compression_allowed && self.decompressor.is_none() {
+                        self.decompressor = Some(Decompressor::new());
+                        let mut compressed = self.plain.split();
+                        compressed.unsplit(src.split());
+                        *src = compressed;
+                    }
+
+                    return Ok(Some(event));
+                }
+            }
+
+            self.plain.clear();
+
+            // Inflating stops at `MAX_INFLATED`, so there may be more.
+            if !self.decompressor.as_ref().is_some_and(Decompressor::has_more) {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // Input still being inflated ends like uncompressed input does.
+        if let Some(event) = self.decode(src)? {
+            return Ok(Some(event));
+        }
+
+        if self.line.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(self.take_line()))
+        }
+    }
+}
+
+impl Encoder<TelnetFrame> for TelnetCodec {
+    type Error = TokioIoError;
+
+    fn encode(&mut self, frame: TelnetFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        if let TelnetFrame::Prompt(data) = frame {
+            let marker = if self.end_of_record { ControlCode::EOR } else { ControlCode::GoAhead };
+            self.encode(TelnetFrame::Data(data), dst)?;
+            return self.encode(TelnetFrame::Command(marker), dst);
+        }
+
+        let frame = match frame {
+            TelnetFrame::Data(data) if self.charset != Charset::Utf8 => {
+                TelnetFrame::Data(self.charset.encode(&String::from_utf8_lossy(&data)))
+            },
+            frame => frame,
+        };
+
+        let starts_compression = matches!(frame, TelnetFrame::Subnegotiation(TelnetOption::Mccp2, _));
+        let ends_compression = matches!(frame, TelnetFrame::Negotiate(Verb::Wont, TelnetOption::Mccp2));
+
+        if ends_compression {
+            if let Some(compressor) = self.compressor.take() {
+                compressor.finish(dst)?;
+            }
+        }
+
+        match self.compressor {
+            Some(ref mut compressor) => {
+                let mut plain = BytesMut::new();
+                encode_plain(frame, &mut plain);
+                compressor.compress(&plain, dst)?;
+            },
+
+            None => encode_plain(frame, dst),
+        }
+
+        if starts_compression && self.compressor.is_none() {
+            self.compressor = Some(Compressor::new());
+        }
+
+        Ok(())
+    }
+}
+
+/// Encode a frame without any compression.
+fn encode_plain(frame: TelnetFrame, dst: &mut BytesMut) {
+    match frame {
+        TelnetFrame::Data(data) => {
+            for &byte in data.iter() {
+                if byte == IAC {
+                    dst.put_u8(IAC);
+                }
+
+                dst.put_u8(byte);
+            }
+        },
+
+        TelnetFrame::Prompt(_) => unreachable!("Prompts are split into data and a command by encode."),
+
+        TelnetFrame::Negotiate(verb, option) => {
+            let verb = match verb {
+                Verb::Will => ControlCode::WILL,
+                Verb::Wont => ControlCode::WONT,
+                Verb::Do => ControlCode::DO,
+                Verb::Dont => ControlCode::DONT,
+            };
+
+            dst.extend_from_slice(&[IAC, verb as u8, option.into()]);
+        },
+
+        TelnetFrame::Command(command) => {
+            dst.extend_from_slice(&[IAC, command as u8]);
+        },
+
+        TelnetFrame::Subnegotiation(option, payload) => {
+            dst.extend_from_slice(&[IAC, ControlCode::SB as u8, option.into()]);
+
+            for &byte in payload.iter() {
+                if byte == IAC {
+                    dst.put_u8(IAC);
+                }
+
+                dst.put_u8(byte);
+            }
+
+            dst.extend_from_slice(&[IAC, ControlCode::SE as u8]);
+        },
+
+        TelnetFrame::Gmcp { package, data } => {
+            encode_plain(TelnetFrame::Subnegotiation(TelnetOption::Gmcp, gmcp::encode(&package, &data)), dst);
+        },
+
+        TelnetFrame::Msdp { variable, value } => {
+            encode_plain(TelnetFrame::Subnegotiation(TelnetOption::Msdp, msdp::encode(&variable, &value)), dst);
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decode_all(bytes: &[u8]) -> Vec<TelnetEvent> {
+        let mut codec = TelnetCodec::new();
+        let mut src = BytesMut::from(bytes);
+        let mut events = vec![];
+
+        while let Some(event) = codec.decode(&mut src).unwrap() {
+            events.push(event);
+        }
+
+        events
+    }
+
+    #[test]
+    fn lines_are_split_on_crlf() {
+        assert_eq!(decode_all(b"look\r\nnext\r\n"), vec![
+            TelnetEvent::Line("look".into()),
+            TelnetEvent::Line("next".into()),
+        ]);
+    }
+
+    #[test]
+    fn repeated_crs_end_one_line() {
+        assert_eq!(decode_all(b"look\r\r\nnext\rsay\r\n"), vec![
+            TelnetEvent::Line("look".into()),
+            TelnetEvent::Line("next".into()),
+            TelnetEvent::Line("say".into()),
+        ]);
+    }
+
+    #[test]
+    fn a_cr_before_a_command_ends_the_line() {
+        assert_eq!(decode_all(b"look\r\xff\xf1say\r\n"), vec![
+            TelnetEvent::Line("look".into()),
+            TelnetEvent::Command(ControlCode::NOP),
+            TelnetEvent::Line("say".into()),
+        ]);
+    }
+
+    #[test]
+    fn commands_are_removed_from_lines() {
+        assert_eq!(decode_all(b"lo\xff\xfd\x01ok\xff\xf1\r\n"), vec![
+            TelnetEvent::Negotiate(Verb::Do, TelnetOption::Echo),
+            TelnetEvent::Command(ControlCode::NOP),
+            TelnetEvent::Line("look".into()),
+        ]);
+    }
+
+    #[test]
+    fn subnegotiation_unescapes_iac() {
+        assert_eq!(decode_all(b"\xff\xfa\x03a\xff\xffb\xff\xf0"), vec![
+            TelnetEvent::Subnegotiation(TelnetOption::SuppressGoAhead, Bytes::from_static(b"a\xffb")),
+        ]);
+    }
+
+    #[test]
+    fn subnegotiation_frames_escape_iac() {
+        let mut codec = TelnetCodec::new();
+        let mut dst = BytesMut::new();
+
+        codec.encode(TelnetFrame::Subnegotiation(TelnetOption::SuppressGoAhead, Bytes::from_static(b"a\xffb")), &mut dst).unwrap();
+
+        assert_eq!(&dst[..], b"\xff\xfa\x03a\xff\xffb\xff\xf0");
+    }
+
+    #[test]
+    fn erase_commands_edit_the_line() {
+        assert_eq!(decode_all(b"lookk\xff\xf7\r\ncaf\xc3\xa9\xff\xf7e\r\nsay\xff\xf8next\r\n"), vec![
+            TelnetEvent::Line("look".into()),
+            TelnetEvent::Line("cafe".into()),
+            TelnetEvent::Line("next".into()),
+        ]);
+    }
+
+    #[test]
+    fn long_lines_are_an_error() {
+        let mut codec = TelnetCodec::new();
+        codec.set_max_line_length(4);
+
+        assert_eq!(codec.decode(&mut BytesMut::from(&b"look\r\n"[..])).unwrap(), Some(TelnetEvent::Line("look".into())));
+
+        let err = codec.decode(&mut BytesMut::from(&b"looks\r\n"[..])).unwrap_err();
+        assert_eq!(LimitExceeded::from_error(&err), Some(LimitExceeded::LineLength));
+    }
+
+    #[test]
+    fn data_frames_escape_iac() {
+        let mut codec = TelnetCodec::new();
+        let mut dst = BytesMut::new();
+
+        codec.encode(TelnetFrame::Data(Bytes::from_static(b"a\xffb")), &mut dst).unwrap();
+
+        assert_eq!(&dst[..], b"a\xff\xffb");
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        assert_eq!(decode_all(b"caf\xe9\r\n"), vec![TelnetEvent::Line("caf\u{fffd}".into())]);
+    }
+
+    #[test]
+    fn prompts_end_with_eor_or_ga() {
+        let mut codec = TelnetCodec::new();
+        let mut dst = BytesMut::new();
+
+        codec.encode(TelnetFrame::Prompt(Bytes::from_static(b"> ")), &mut dst).unwrap();
+        codec.set_end_of_record(true);
+        codec.encode(TelnetFrame::Prompt(Bytes::from_static(b"> ")), &mut dst).unwrap();
+
+        assert_eq!(&dst[..], b"> \xff\xf9> \xff\xef");
+    }
+
+    #[test]
+    fn mccp3_input_is_inflated() {
+        use flate2::{Compress, Compression, FlushCompress};
+
+        let mut compress = Compress::new(Compression::default(), true);
+        let mut compressed = Vec::with_capacity(64);
+        compress.compress_vec(b"look\r\n", &mut compressed, FlushCompress::Finish).unwrap();
+
+        let mut input = b"\xff\xfa\x57\xff\xf0".to_vec();
+        input.extend_from_slice(&compressed);
+        input.extend_from_slice(b"next\r\n");
+
+        let mut codec = TelnetCodec::new();
+        codec.set_input_compression_allowed(true);
+        let mut src = BytesMut::from(&input[..]);
+        let mut events = vec![];
+
+        while let Some(event) = codec.decode(&mut src).unwrap() {
+            events.push(event);
+        }
+
+        assert_eq!(events, vec![
+            TelnetEvent::Subnegotiation(TelnetOption::Mccp3, Bytes::new()),
+            TelnetEvent::Line("look".into()),
+            TelnetEvent::Line("next".into()),
+        ]);
+    }
+
+    #[test]
+    fn mccp3_input_can_end_with_the_connection() {
+        use flate2::{Compress, Compression, FlushCompress};
+
+        let mut compress = Compress::new(Compression::default(), true);
+        let mut compressed = Vec::with_capacity(64);
+        compress.compress_vec(b"look\r\nquit", &mut compressed, FlushCompress::Sync).unwrap();
+
+        let mut codec = TelnetCodec::new();
+        codec.set_input_compression_allowed(true);
+        assert!(codec.decode(&mut BytesMut::from(&b"\xff\xfa\x57\xff\xf0"[..])).unwrap().is_some());
+
+        let mut src = BytesMut::from(&compressed[..]);
+        assert_eq!(codec.decode_eof(&mut src).unwrap(), Some(TelnetEvent::Line("look".into())));
+        assert_eq!(codec.decode_eof(&mut src).unwrap(), Some(TelnetEvent::Line("quit".into())));
+        assert_eq!(codec.decode_eof(&mut src).unwrap(), None);
+    }
+
+    #[test]
+    fn untaken_input_parses_the_same() {
+        let mut codec = TelnetCodec::new();
+        let mut src = BytesMut::from(&b"done\r\nlo\xff\xffk\r"[..]);
+        assert_eq!(codec.decode(&mut src).unwrap(), Some(TelnetEvent::Line("done".into())));
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+        let input = codec.take_input(BytesMut::from(&b"\nsay\r\n"[..])).unwrap();
+        assert_eq!(decode_all(&input), decode_all(b"lo\xff\xffk\r\nsay\r\n"));
+        assert_eq!(decode_all(&input).len(), 2);
+    }
+
+    #[test]
+    fn corrupt_mccp3_input_is_an_error() {
+        let mut codec = TelnetCodec::new();
+        codec.set_input_compression_allowed(true);
+        let mut src = BytesMut::from(&b"\xff\xfa\x57\xff\xf0"[..]);
+
+        assert!(codec.decode(&mut src).unwrap().is_some());
+        assert!(codec.decode(&mut BytesMut::from(&b"not zlib at all"[..])).is_err());
+    }
+
+    #[test]
+    fn mccp3_input_is_inflated_a_little_at_a_time() {
+        use flate2::{Compress, Compression, FlushCompress};
+
+        let mut compress = Compress::new(Compression::default(), true);
+        let mut compressed = Vec::with_capacity(4096);
+        compress.compress_vec(&b"look\r\n".repeat(100_000), &mut compressed, FlushCompress::Sync).unwrap();
+
+        let mut codec = TelnetCodec::new();
+        codec.set_input_compression_allowed(true);
+        assert!(codec.decode(&mut BytesMut::from(&b"\xff\xfa\x57\xff\xf0"[..])).unwrap().is_some());
+
+        let mut src = BytesMut::from(&compressed[..]);
+        let mut lines = 0;
+
+        while let Some(event) = codec.decode(&mut src).unwrap() {
+            assert_eq!(event, TelnetEvent::Line("look".into()));
+            assert!(codec.plain.len() <= MAX_INFLATED);
+            lines += 1;
+        }
+
+        assert_eq!(lines, 100_000);
+    }
+
+    #[test]
+    fn mccp3_input_that_inflates_to_a_long_line_is_an_error() {
+        use flate2::{Compress, Compression, FlushCompress};
+
+        let mut compress = Compress::new(Compression::default(), true);
+        let mut compressed = Vec::with_capacity(4096);
+        compress.compress_vec(&vec![b'a'; 1024 * 1024], &mut compressed, FlushCompress::Sync).unwrap();
+
+        let mut codec = TelnetCodec::new();
+        codec.set_input_compression_allowed(true);
+        assert!(codec.decode(&mut BytesMut::from(&b"\xff\xfa\x57\xff\xf0"[..])).unwrap().is_some());
+
+        let err = codec.decode(&mut BytesMut::from(&compressed[..])).unwrap_err();
+        assert_eq!(LimitExceeded::from_error(&err), Some(LimitExceeded::LineLength));
+    }
+
+    #[test]
+    fn events_can_span_reads() {
+        let mut codec = TelnetCodec::new();
+
+        assert_eq!(codec.decode(&mut BytesMut::from(&b"lo\xff"[..])).unwrap(), None);
+        assert_eq!(codec.decode(&mut BytesMut::from(&b"\xfb"[..])).unwrap(), None);
+        assert_eq!(
+            codec.decode(&mut BytesMut::from(&b"\x03ok\r\n"[..])).unwrap(),
+            Some(TelnetEvent::Negotiate(Verb::Will, TelnetOption::SuppressGoAhead))
+        );
+    }
+}
@@ -1,5 +1,11 @@
+//! The commands that may follow an IAC byte.
+
 #[repr(u8)]
-enum ControlCode {
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ControlCode {
+    /// End of record. https://tools.ietf.org/html/rfc885
+    EOR = 239,
+
     /// End of subnegotiation parameters.
     SE = 240,
 
@@ -50,4 +56,29 @@ enum ControlCode {
 
     /// Data Byte 255.
     IAC = 255
-}
\ No newline at end of file
+}
+
+impl ControlCode {
+    pub fn from_u8(byte: u8) -> Option<Self> {
+        Some(match byte {
+            239 => Self::EOR,
+            240 => Self::SE,
+            241 => Self::NOP,
+            242 => Self::DataMark,
+            243 => Self::Break,
+            244 => Self::InterruptProcess,
+            245 => Self::AbortOutput,
+            246 => Self::AreYouThere,
+            247 => Self::EraseCharacter,
+            248 => Self::EraseLine,
+            249 => Self::GoAhead,
+            250 => Self::SB,
+            251 => Self::WILL,
+            252 => Self::WONT,
+            253 => Self::DO,
+            254 => Self::DONT,
+            255 => Self::IAC,
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,57 @@
+//! Generic MUD Communication Protocol.
+//!
+//! https://www.gammon.com.au/gmcp
+//!
+//! Each message is a package name such as `Room.Info`, optionally followed by
+//! a space and JSON data, sent as IAC SB GMCP <message> IAC SE.
+
+use bytes::Bytes;
+
+/// Split the payload of a GMCP subnegotiation into package and data.
+pub(crate) fn parse(payload: &[u8]) -> Option<(String, String)> {
+    let payload = std::str::from_utf8(payload).ok()?;
+
+    let (package, data) = match payload.find(char::is_whitespace) {
+        Some(index) => (&payload[..index], payload[index..].trim()),
+        None => (payload, ""),
+    };
+
+    if package.is_empty() {
+        None
+    } else {
+        Some((package.to_string(), data.to_string()))
+    }
+}
+
+/// Build the payload of a GMCP subnegotiation.
+pub(crate) fn encode(package: &str, data: &str) -> Bytes {
+    if data.is_empty() {
+        Bytes::copy_from_slice(package.as_bytes())
+    } else {
+        Bytes::from(format!("{} {}", package, data))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_with_data() {
+        assert_eq!(
+            parse(br#"Core.Supports.Set ["Room 1", "Char 1"]"#),
+            Some(("Core.Supports.Set".to_string(), r#"["Room 1", "Char 1"]"#.to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_without_data() {
+        assert_eq!(parse(b"Core.Ping"), Some(("Core.Ping".to_string(), String::new())));
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        let payload = encode("Room.Info", r#"{"num":1}"#);
+        assert_eq!(parse(&payload), Some(("Room.Info".to_string(), r#"{"num":1}"#.to_string())));
+    }
+}
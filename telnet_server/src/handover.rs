@@ -0,0 +1,91 @@
+//! Moving a connection to another process, such as a new version of the
+//! server that replaces this one without disconnecting players.
+//!
+//! `TelnetStream::detach` ends the stream, returning its socket and what was
+//! negotiated with the client. The new process passes both to
+//! `TelnetStream::resume` to continue where the old one left off.
+//!
+//! Compression can't be handed over, so `detach` ends it and `resume` offers
+//! it again. Input is shown again too, since whether to hide it is up to the
+//! new process. Input that was received but not yet handled is handed
+//! over with the rest.
+
+use crate::charset::Charset;
+use crate::options::TelnetOption;
+
+/// What was negotiated on a detached `TelnetStream`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StreamState {
+    /// Options this server performs.
+    pub local: Vec<TelnetOption>,
+
+    /// Options the client performs.
+    pub remote: Vec<TelnetOption>,
+
+    pub charset: Charset,
+
+    /// MSDP variables the client asked to have reported.
+    pub msdp_reported: Vec<String>,
+
+    /// Input received but not yet handled, such as a partly typed line.
+    pub input: Vec<u8>,
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use futures::{SinkExt as _, StreamExt as _};
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::TcpStream;
+
+    use crate::{OptionChange, Party, TelnetEvent, TelnetFrame, TelnetListener, TelnetOption, TelnetStream};
+
+    #[tokio::test]
+    async fn resumed_streams_keep_their_options() {
+        let mut listener = TelnetListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (accepted, _addr) = listener.accept().await.unwrap();
+        let (mut stream, _) = accepted.handshake().await.unwrap();
+
+        // IAC DO END-OF-RECORD
+        client.write_all(&[255, 253, 25]).await.unwrap();
+        let change = OptionChange { option: TelnetOption::EndOfRecord, party: Party::Local, enabled: true };
+        assert_eq!(stream.next().await.unwrap().unwrap(), TelnetEvent::OptionChange(change));
+
+        let (tcp, state) = stream.detach().await.unwrap();
+        assert!(state.local.contains(&TelnetOption::EndOfRecord));
+
+        let mut stream = TelnetStream::resume(tcp, state).unwrap();
+        assert!(stream.negotiator().local_enabled(TelnetOption::EndOfRecord));
+        stream.send(TelnetFrame::Prompt("> ".into())).await.unwrap();
+        drop(stream);
+
+        let mut received = vec![];
+        client.read_to_end(&mut received).await.unwrap();
+
+        // The prompt ends in IAC EOR without the options being offered again.
+        assert!(received.ends_with(b"> \xff\xef"));
+        assert!(!received.ends_with(b"\xff\xfb\x19> \xff\xef"));
+    }
+
+    #[tokio::test]
+    async fn resumed_streams_keep_unread_input() {
+        let mut listener = TelnetListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (accepted, _addr) = listener.accept().await.unwrap();
+        let (mut stream, _) = accepted.handshake().await.unwrap();
+
+        client.write_all(b"look\r\nsa").await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), TelnetEvent::Line("look".into()));
+
+        let (tcp, state) = stream.detach().await.unwrap();
+        assert_eq!(state.input, b"sa");
+
+        let mut stream = TelnetStream::resume(tcp, state).unwrap();
+        client.write_all(b"y hi\r\n").await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), TelnetEvent::Line("say hi".into()));
+    }
+}
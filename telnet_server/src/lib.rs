@@ -1,39 +1,786 @@
-use std::net::SocketAddr;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
+use bytes::{Bytes, BytesMut};
+use futures::{ready, Sink, Stream};
 use tokio::{
-    io::{Error as TokioIoError},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt as _, Error as TokioIoError, ErrorKind},
     net::{
         TcpListener,
         TcpStream,
         ToSocketAddrs,
     }
 };
+use tokio_util::codec::Framed;
 
-/// TELNET listener. Currently stub that **does** not implement TELNET at all!
+mod charset;
+pub mod client;
+pub mod codec;
+pub mod control_codes;
+mod gmcp;
+mod handover;
+mod limits;
+mod mccp;
+mod msdp;
+mod mssp;
+mod naws;
+pub mod negotiation;
+pub mod options;
+mod proxy;
+pub mod tls;
+mod ttype;
+
+pub use charset::Charset;
+pub use client::TelnetClient;
+pub use codec::{TelnetCodec, TelnetEvent, TelnetFrame};
+pub use control_codes::ControlCode;
+pub use handover::StreamState;
+pub use limits::{LimitExceeded, Limiter, Limits, Rate, Slot};
+pub use msdp::MsdpValue;
+pub use mssp::MsspVariables;
+pub use negotiation::{Negotiator, OptionChange, Party, Verb};
+pub use options::TelnetOption;
+pub use tls::TlsAcceptor;
+pub use ttype::{Mtts, TerminalType};
+
+/// TELNET listener.
 /// 
 /// ## Protocols
 /// 
 /// Telnet: https://tools.ietf.org/html/rfc854
+/// Telnet Q Method: https://tools.ietf.org/html/rfc1143
 /// Telnet over UTF-8: https://tools.ietf.org/html/rfc5198
+/// Telnet Charset Option: https://tools.ietf.org/html/rfc2066
 /// Telnet Window Size Options: https://tools.ietf.org/html/rfc1073
+/// Telnet Terminal Type Option: https://tools.ietf.org/html/rfc1091
+/// Mud Terminal Type Standard: https://tintin.sourceforge.io/protocols/mtts/
 /// Telnet End of Record Option: https://tools.ietf.org/html/rfc885
 /// Telnet Echo Option: https://tools.ietf.org/html/rfc857
+/// Mud Client Compression Protocol: https://tintin.sourceforge.io/protocols/mccp/
+/// Generic MUD Communication Protocol: https://www.gammon.com.au/gmcp
+/// MUD Server Data Protocol: https://tintin.sourceforge.io/protocols/msdp/
+/// Mud Server Status Protocol: https://tintin.sourceforge.io/protocols/mssp/
+/// MUD eXtension Protocol: https://www.zuggsoft.com/zmud/mxp.htm
+/// PROXY protocol: https://www.haproxy.org/download/2.2/doc/proxy-protocol.txt
+///
+/// Connections are held to the default `Limits` unless `set_limiter` is
+/// called.
 pub struct TelnetListener {
-    tcp: TcpListener
+    tcp: TcpListener,
+    tls: Option<TlsAcceptor>,
+    limiter: Limiter,
+    /// Addresses of load balancers that start connections with a PROXY header.
+    trusted_proxies: Vec<IpAddr>,
+    handshake_timeout: Duration,
 }
 
-pub type TelnetStream = TcpStream;
+/// How long `Accepted::handshake` waits on the client unless the listener's
+/// timeout is changed.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
 impl TelnetListener {
     pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<TelnetListener, TokioIoError> {
-        TcpListener::bind(addr).await.map(|tcp| TelnetListener { tcp })
+        Self::bind_with(addr, None).await
+    }
+
+    /// Bind a listener whose connections use TLS.
+    pub async fn bind_tls<A: ToSocketAddrs>(addr: A, tls: TlsAcceptor) -> Result<TelnetListener, TokioIoError> {
+        Self::bind_with(addr, Some(tls)).await
+    }
+
+    async fn bind_with<A: ToSocketAddrs>(addr: A, tls: Option<TlsAcceptor>) -> Result<TelnetListener, TokioIoError> {
+        let tcp = TcpListener::bind(addr).await?;
+        Ok(TelnetListener { tcp, tls, limiter: Limiter::default(), trusted_proxies: vec![], handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT })
+    }
+
+    /// Enforce the limits of `limiter`. Listeners given clones of the same
+    /// limiter count connections together.
+    pub fn set_limiter(&mut self, limiter: Limiter) {
+        self.limiter = limiter;
+    }
+
+    /// Expect connections from these addresses to start with a PROXY protocol
+    /// header, version 1 or 2, and use the client address from it. Without
+    /// one, the connection is closed.
+    pub fn set_trusted_proxies(&mut self, trusted_proxies: Vec<IpAddr>) {
+        self.trusted_proxies = trusted_proxies;
+    }
+
+    /// Set how long the PROXY header and TLS handshake may take together
+    /// before the connection is closed.
+    pub fn set_handshake_timeout(&mut self, timeout: Duration) {
+        self.handshake_timeout = timeout;
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, TokioIoError> {
+        self.tcp.local_addr()
+    }
+
+    /// Accept a connection. Call `Accepted::handshake` on it, preferably in
+    /// its own task since a TLS handshake waits on the client.
+    ///
+    /// The address returned is the one the connection came from. For trusted
+    /// proxies, the client's address comes from `Accepted::handshake`.
+    ///
+    /// Connections over the limits are sent the reason and closed without
+    /// being returned. Over TLS, they are closed without a message.
+    pub async fn accept(&mut self) -> Result<(Accepted, SocketAddr), TokioIoError> {
+        loop {
+            let (mut tcp, addr) = self.tcp.accept().await?;
+            let proxied = self.trusted_proxies.contains(&addr.ip());
+
+            // Connections through a proxy are counted against the client's
+            // address once the handshake has read it.
+            let admitted = self.limiter.accept().and_then(|()| {
+                if proxied {
+                    Ok(None)
+                } else {
+                    self.limiter.admit(addr.ip()).map(Some)
+                }
+            });
+
+            match admitted {
+                Ok(slot) => {
+                    println!("New connection");
+
+                    let accepted = Accepted { tcp, addr, tls: self.tls.clone(), limiter: self.limiter.clone(), slot, timeout: self.handshake_timeout };
+                    return Ok((accepted, addr));
+                },
+
+                Err(exceeded) => refuse(&mut tcp, addr, exceeded, self.tls.is_some()).await,
+            }
+        }
+    }
+}
+
+/// Tell a connection over the limits why it is being closed.
+async fn refuse(tcp: &mut TcpStream, addr: SocketAddr, exceeded: LimitExceeded, tls: bool) {
+    eprintln!("Refusing connection from {}: {}", addr, exceeded);
+
+    // The message fits in the socket's empty send buffer, so this does not
+    // wait on the client.
+    if !tls {
+        let _ = tcp.write_all(format!("{}\r\n", exceeded).as_bytes()).await;
+    }
+}
+
+/// A connection that may still need its PROXY header and TLS handshake.
+pub struct Accepted {
+    tcp: TcpStream,
+    addr: SocketAddr,
+    tls: Option<TlsAcceptor>,
+    limiter: Limiter,
+    /// Missing for connections from trusted proxies until the client's
+    /// address is known.
+    slot: Option<limits::Slot>,
+    timeout: Duration,
+}
+
+impl Accepted {
+    /// Read the PROXY header, if the connection came from a trusted proxy,
+    /// do the TLS handshake, if there is one, and start TELNET.
+    ///
+    /// Returns the client's address along with the stream. Clients that take
+    /// longer than the listener's handshake timeout are closed with a
+    /// `TimedOut` error, no longer counting against the limits.
+    pub async fn handshake(self) -> Result<(TelnetStream, SocketAddr), TokioIoError> {
+        let addr = self.addr;
+
+        // The connection and its slot are dropped with the future.
+        match tokio::time::timeout(self.timeout, self.handshake_in_time()).await {
+            Ok(result) => result,
+            Err(_) => Err(TokioIoError::new(ErrorKind::TimedOut, format!("handshake with {} timed out", addr))),
+        }
+    }
+
+    async fn handshake_in_time(mut self) -> Result<(TelnetStream, SocketAddr), TokioIoError> {
+        let (slot, addr) = match self.slot {
+            Some(slot) => (slot, self.addr),
+
+            None => {
+                let addr = proxy::read_header(&mut self.tcp).await?.unwrap_or(self.addr);
+
+                match self.limiter.admit(addr.ip()) {
+                    Ok(slot) => (slot, addr),
+                    Err(exceeded) => {
+                        refuse(&mut self.tcp, addr, exceeded, self.tls.is_some()).await;
+                        return Err(exceeded.into());
+                    },
+                }
+            },
+        };
+
+        let mut stream = match self.tls {
+            Some(tls) => TelnetStream::new(tls.accept(self.tcp).await?),
+            None => TelnetStream::new(self.tcp),
+        };
+
+        stream.set_limits(self.limiter.limits());
+        stream.slot = Some(slot);
+        Ok((stream, addr))
+    }
+}
+
+/// The reply to IAC AYT, showing the server is still running.
+pub const AYT_REPLY: &[u8] = b"\r\n[Yes]\r\n";
+
+/// Frames the stream may have waiting to send on its own before it stops
+/// reading, so a client that asks for replies without reading them can't
+/// make them pile up.
+const MAX_PENDING: usize = 64;
+
+/// A byte stream TELNET can run over, such as TCP or TLS over TCP.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin + 'static {
+    /// For getting the TCP stream back out of a `TelnetStream`.
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static> Transport for T {
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A TELNET connection.
+///
+/// As a `Stream`, it yields `TelnetEvent`s. Option negotiation is answered
+/// internally and reported as `TelnetEvent::OptionChange`. As a `Sink`, it
+/// accepts `TelnetFrame`s.
+///
+/// Sending `TelnetFrame::Negotiate(Verb::Will, TelnetOption::Echo)` makes the
+/// client stop echoing input, which is how passwords are hidden. The stream
+/// never echoes input itself. Clients cannot turn ECHO on by asking for it.
+///
+/// Are You There is answered with `AYT_REPLY` and not reported. MSSP is
+/// answered once `serve_mssp` is called, including the `MSSP-REQUEST` line.
+///
+/// Input over the line length or line rate `Limits` is an error that
+/// `LimitExceeded::from_error` recognizes. The reason is sent to the client
+/// when the stream is closed.
+pub struct TelnetStream {
+    framed: Framed<Box<dyn Transport>, TelnetCodec>,
+    negotiator: Negotiator,
+    /// Frames the stream needs to send on its own, such as negotiation replies.
+    pending: VecDeque<TelnetFrame>,
+    /// Events to report before reading more, such as the rest of several
+    /// MSDP variables set at once.
+    events: VecDeque<TelnetEvent>,
+    /// Whether frames from `pending` have been written but not flushed.
+    needs_flush: bool,
+    msdp: msdp::Msdp,
+    terminal_types: ttype::Cycle,
+    lines: limits::Rate,
+    lines_per_second: u32,
+    /// Counts this connection against its IP address while it is open.
+    slot: Option<limits::Slot>,
+    /// The server's status for MSSP, if it is served.
+    mssp_status: Option<Box<dyn Fn() -> MsspVariables + Send>>,
+}
+
+impl TelnetStream {
+    /// Start TELNET over a transport, offering the options this server supports.
+    pub fn new<T: Transport + 'static>(transport: T) -> Self {
+        let mut stream = Self {
+            framed: Framed::new(Box::new(transport), TelnetCodec::new()),
+            negotiator: Negotiator::new(),
+            pending: VecDeque::new(),
+            events: VecDeque::new(),
+            needs_flush: false,
+            msdp: msdp::Msdp::default(),
+            terminal_types: ttype::Cycle::default(),
+            lines: limits::Rate::new(),
+            lines_per_second: Limits::default().lines_per_second,
+            slot: None,
+            mssp_status: None,
+        };
+
+        stream.negotiator.support_remote(TelnetOption::Naws);
+        let request = stream.negotiator.enable_remote(TelnetOption::Naws);
+        stream.queue_negotiation(request);
+
+        stream.negotiator.support_remote(TelnetOption::TerminalType);
+        let request = stream.negotiator.enable_remote(TelnetOption::TerminalType);
+        stream.queue_negotiation(request);
+
+        stream.negotiator.support_remote(TelnetOption::Charset);
+        stream.negotiator.support_local(TelnetOption::Charset);
+        let request = stream.negotiator.enable_local(TelnetOption::Charset);
+        stream.queue_negotiation(request);
+
+        stream.negotiator.support_local(TelnetOption::EndOfRecord);
+        let request = stream.negotiator.enable_local(TelnetOption::EndOfRecord);
+        stream.queue_negotiation(request);
+
+        stream.negotiator.support_local(TelnetOption::Mccp2);
+        let request = stream.negotiator.enable_local(TelnetOption::Mccp2);
+        stream.queue_negotiation(request);
+
+        stream.negotiator.support_local(TelnetOption::Mccp3);
+        let request = stream.negotiator.enable_local(TelnetOption::Mccp3);
+        stream.queue_negotiation(request);
+
+        stream.negotiator.support_local(TelnetOption::Gmcp);
+        let request = stream.negotiator.enable_local(TelnetOption::Gmcp);
+        stream.queue_negotiation(request);
+
+        stream.negotiator.support_local(TelnetOption::Msdp);
+        let request = stream.negotiator.enable_local(TelnetOption::Msdp);
+        stream.queue_negotiation(request);
+
+        stream.negotiator.support_local(TelnetOption::Mxp);
+        let request = stream.negotiator.enable_local(TelnetOption::Mxp);
+        stream.queue_negotiation(request);
+
+        stream
+    }
+
+    /// Set the line length and line rate limits. The other limits are for
+    /// listeners.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.framed.codec_mut().set_max_line_length(limits.line_length);
+        self.lines_per_second = limits.lines_per_second;
+    }
+
+    /// Let the client REPORT these MSDP variables before they are first
+    /// sent. Other variables can only be reported once they have been sent.
+    pub fn set_msdp_reportable(&mut self, names: &[&str]) {
+        self.msdp.set_reportable(names.iter().map(|name| name.to_string()));
+    }
+
+    /// Offer MSSP and answer `MSSP-REQUEST`, reading the variables from
+    /// `status` each time they are asked for.
+    pub fn serve_mssp(&mut self, status: impl Fn() -> MsspVariables + Send + 'static) {
+        self.mssp_status = Some(Box::new(status));
+
+        self.negotiator.support_local(TelnetOption::Mssp);
+        let request = self.negotiator.enable_local(TelnetOption::Mssp);
+        self.queue_negotiation(request);
+    }
+
+    /// Whether `detach` can succeed. Only TELNET over plain TCP can be
+    /// detached, and only on unix.
+    pub fn can_detach(&self) -> bool {
+        cfg!(unix) && self.framed.get_ref().as_any().is::<TcpStream>()
+    }
+
+    /// End the stream without closing the connection, so that another
+    /// process can `resume` it. Only TELNET over plain TCP can be detached.
+    ///
+    /// Compression is ended and input is shown again before the socket is
+    /// returned. The socket is not closed on exec. Handing sockets over needs
+    /// unix, so elsewhere this always fails.
+    pub async fn detach(mut self) -> Result<(std::net::TcpStream, StreamState), TokioIoError> {
+        use futures::SinkExt as _;
+
+        if self.framed.codec().is_compressing() {
+            let request = self.negotiator.disable_local(TelnetOption::Mccp2);
+            self.queue_negotiation(request);
+        }
+
+        for option in &[TelnetOption::Mccp3, TelnetOption::Echo] {
+            let request = self.negotiator.disable_local(*option);
+            self.queue_negotiation(request);
+        }
+
+        self.flush().await?;
+
+        let state = StreamState {
+            local: self.negotiator.enabled(Party::Local),
+            remote: self.negotiator.enabled(Party::Remote),
+            charset: self.framed.codec().charset(),
+            msdp_reported: self.msdp.reported(),
+            input: vec![],
+        };
+
+        let mut parts = self.framed.into_parts();
+        let state = StreamState { input: parts.codec.take_input(parts.read_buf)?, ..state };
+
+        let tcp = parts.io.into_any().downcast::<TcpStream>()
+        .map_err(|_| TokioIoError::new(std::io::ErrorKind::Unsupported, "only TELNET over plain TCP can be detached"))?;
+
+        Ok((duplicate_for_exec(&tcp)?, state))
+    }
+
+    /// Continue a stream detached by `detach`, probably in another process.
+    /// Options are not offered again, except for compression.
+    ///
+    /// The connection does not count against the `Limits` of a listener.
+    pub fn resume(tcp: std::net::TcpStream, state: StreamState) -> Result<Self, TokioIoError> {
+        let mut stream = Self::new(TcpStream::from_std(tcp)?);
+
+        // The client already answered the offers made by `new`.
+        stream.pending.clear();
+        stream.negotiator.restore(&state.local, &state.remote);
+
+        let end_of_record = stream.negotiator.local_enabled(TelnetOption::EndOfRecord);
+        stream.framed.codec_mut().set_end_of_record(end_of_record);
+        stream.framed.codec_mut().set_charset(state.charset);
+        stream.msdp.set_reported(state.msdp_reported);
+
+        // Input the old stream hadn't handled yet is read first.
+        let mut parts = stream.framed.into_parts();
+        parts.read_buf = BytesMut::from(&state.input[..]);
+        stream.framed = Framed::from_parts(parts);
+
+        for option in &[TelnetOption::Mccp2, TelnetOption::Mccp3] {
+            let request = stream.negotiator.enable_local(*option);
+            stream.queue_negotiation(request);
+        }
+
+        Ok(stream)
     }
 
-    pub async fn accept(&mut self) -> Result<(TelnetStream, SocketAddr), TokioIoError> {
-        let res = self.tcp.accept().await;
+    pub fn negotiator(&self) -> &Negotiator {
+        &self.negotiator
+    }
+
+    /// Use this to mark which options are supported. To request option
+    /// changes, send `TelnetFrame::Negotiate` instead.
+    pub fn negotiator_mut(&mut self) -> &mut Negotiator {
+        &mut self.negotiator
+    }
+
+    fn queue_negotiation(&mut self, command: Option<(Verb, TelnetOption)>) {
+        if let Some((verb, option)) = command {
+            self.pending.push_back(TelnetFrame::Negotiate(verb, option));
+        }
+    }
+
+    /// Handle an event from the codec, returning the event to hand out, if any.
+    fn handle_event(&mut self, event: TelnetEvent) -> Option<TelnetEvent> {
+        match event {
+            TelnetEvent::Negotiate(verb, option) => {
+                let outcome = self.negotiator.receive(verb, option);
+                self.queue_negotiation(outcome.reply);
+
+                // Compression starts right after this subnegotiation. It is
+                // ended by the WONT that the negotiator replies to DONT with.
+                if let Some(OptionChange { option: TelnetOption::Mccp2, party: Party::Local, enabled: true }) = outcome.change {
+                    self.pending.push_back(TelnetFrame::Subnegotiation(TelnetOption::Mccp2, Bytes::new()));
+                }
+
+                // Clients wait for this subnegotiation before parsing MXP tags.
+                if let Some(OptionChange { option: TelnetOption::Mxp, party: Party::Local, enabled: true }) = outcome.change {
+                    self.pending.push_back(TelnetFrame::Subnegotiation(TelnetOption::Mxp, Bytes::new()));
+                }
+
+                if let Some(OptionChange { option: TelnetOption::TerminalType, party: Party::Remote, enabled: true }) = outcome.change {
+                    self.pending.push_back(TelnetFrame::Subnegotiation(TelnetOption::TerminalType, ttype::send()));
+                }
+
+                if let Some(OptionChange { option: TelnetOption::Charset, party: Party::Local, enabled: true }) = outcome.change {
+                    self.pending.push_back(TelnetFrame::Subnegotiation(TelnetOption::Charset, charset::request()));
+                }
+
+                if let Some(OptionChange { option: TelnetOption::Mssp, party: Party::Local, enabled: true }) = outcome.change {
+                    if let Some(ref status) = self.mssp_status {
+                        self.pending.push_back(TelnetFrame::Subnegotiation(TelnetOption::Mssp, mssp::encode(&status())));
+                    }
+                }
+
+                if let Some(OptionChange { option: TelnetOption::EndOfRecord, party: Party::Local, enabled }) = outcome.change {
+                    self.framed.codec_mut().set_end_of_record(enabled);
+                }
+
+                // The client starts MCCP3 itself once it knows we accept it.
+                if let Some(OptionChange { option: TelnetOption::Mccp3, party: Party::Local, enabled }) = outcome.change {
+                    self.framed.codec_mut().set_input_compression_allowed(enabled);
+                }
+
+                outcome.change.map(TelnetEvent::OptionChange)
+            },
+
+            TelnetEvent::Subnegotiation(TelnetOption::Naws, payload) => {
+                let (width, height) = naws::parse(&payload)?;
+                Some(TelnetEvent::WindowSize { width, height })
+            },
+
+            TelnetEvent::Subnegotiation(TelnetOption::TerminalType, payload) => {
+                match self.terminal_types.receive(ttype::parse(&payload)?) {
+                    ttype::Step::AskAgain => {
+                        self.pending.push_back(TelnetFrame::Subnegotiation(TelnetOption::TerminalType, ttype::send()));
+                        None
+                    },
+                    ttype::Step::Done(terminal_type) => Some(TelnetEvent::TerminalType(terminal_type)),
+                    ttype::Step::Ignore => None,
+                }
+            },
+
+            TelnetEvent::Subnegotiation(TelnetOption::Charset, payload) => {
+                let charset = match charset::parse(&payload)? {
+                    charset::Message::Request(offered) => {
+                        let (reply, charset) = charset::answer(&offered);
+                        self.pending.push_back(TelnetFrame::Subnegotiation(TelnetOption::Charset, reply));
+                        charset?
+                    },
+                    charset::Message::Accepted(name) => Charset::from_name(&name)?,
+                    charset::Message::Rejected => return None,
+                };
+
+                self.framed.codec_mut().set_charset(charset);
+                Some(TelnetEvent::Charset(charset))
+            },
+
+            TelnetEvent::Subnegotiation(TelnetOption::Gmcp, payload) => {
+                let (package, data) = gmcp::parse(&payload)?;
+                Some(TelnetEvent::Gmcp { package, data })
+            },
+
+            TelnetEvent::Subnegotiation(TelnetOption::Msdp, payload) => {
+                let msdp::Received { replies, variables } = self.msdp.receive(&payload);
+
+                for reply in replies {
+                    self.pending.push_back(TelnetFrame::Subnegotiation(TelnetOption::Msdp, reply));
+                }
+
+                self.events.extend(variables.into_iter().map(|(variable, value)| TelnetEvent::Msdp { variable, value }));
+                self.events.pop_front()
+            },
+
+            TelnetEvent::Line(ref line) if line == mssp::REQUEST && self.mssp_status.is_some() => {
+                let status = self.mssp_status.as_ref().expect("Checked MSSP is served.");
+                self.pending.push_back(TelnetFrame::Data(mssp::plain_text(&status())));
+                None
+            },
+
+            // The codec starts inflating after this; there is nothing else to do.
+            TelnetEvent::Subnegotiation(TelnetOption::Mccp3, _) => None,
+
+            TelnetEvent::Command(ControlCode::AreYouThere) => {
+                self.pending.push_back(TelnetFrame::Data(Bytes::from_static(AYT_REPLY)));
+                None
+            },
+
+            event => Some(event),
+        }
+    }
+
+    /// Tell the client which limit it broke before the stream is closed.
+    fn queue_limit_exceeded(&mut self, exceeded: LimitExceeded) {
+        let message = format!("\r\n{}\r\n", exceeded);
+        self.pending.push_back(TelnetFrame::Data(message.into()));
+    }
+
+    /// Write out the frames in `pending`.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), TokioIoError>> {
+        while !self.pending.is_empty() {
+            ready!(Pin::new(&mut self.framed).poll_ready(cx))?;
+
+            let frame = self.pending.pop_front().expect("Checked pending is not empty.");
+            Pin::new(&mut self.framed).start_send(frame)?;
+            self.needs_flush = true;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Duplicate the socket of a detached stream. Unlike the original, the
+/// duplicate is inherited across exec. It stays open when the original is
+/// dropped.
+#[cfg(unix)]
+fn duplicate_for_exec(tcp: &TcpStream) -> Result<std::net::TcpStream, TokioIoError> {
+    use std::os::unix::io::{AsRawFd as _, FromRawFd as _};
+
+    let fd = unsafe { libc::dup(tcp.as_raw_fd()) };
+
+    if fd < 0 {
+        return Err(TokioIoError::last_os_error());
+    }
+
+    Ok(unsafe { std::net::TcpStream::from_raw_fd(fd) })
+}
+
+#[cfg(not(unix))]
+fn duplicate_for_exec(_tcp: &TcpStream) -> Result<std::net::TcpStream, TokioIoError> {
+    Err(TokioIoError::new(std::io::ErrorKind::Unsupported, "detaching needs unix"))
+}
+
+impl Stream for TelnetStream {
+    type Item = Result<TelnetEvent, TokioIoError>;
 
-        println!("New connection");
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
 
-        res
+        loop {
+            // Replies are written out opportunistically. If the socket is not
+            // ready for them, keep reading; the task is woken for both.
+            if let Poll::Ready(result) = this.poll_pending(cx) {
+                result?;
+
+                if this.needs_flush {
+                    if let Poll::Ready(result) = Pin::new(&mut this.framed).poll_flush(cx) {
+                        result?;
+                        this.needs_flush = false;
+                    }
+                }
+            }
+
+            if let Some(event) = this.events.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+
+            // The task is woken once the socket can take more replies.
+            if this.pending.len() >= MAX_PENDING {
+                return Poll::Pending;
+            }
+
+            let event = match ready!(Pin::new(&mut this.framed).poll_next(cx)) {
+                Some(Ok(event)) => event,
+                Some(Err(err)) => {
+                    if let Some(exceeded) = LimitExceeded::from_error(&err) {
+                        this.queue_limit_exceeded(exceeded);
+                    }
+
+                    return Poll::Ready(Some(Err(err)));
+                },
+                None => return Poll::Ready(None),
+            };
+
+            if let TelnetEvent::Line(_) = event {
+                if this.lines.exceeded(this.lines_per_second) {
+                    this.queue_limit_exceeded(LimitExceeded::LineRate);
+                    return Poll::Ready(Some(Err(LimitExceeded::LineRate.into())));
+                }
+            }
+
+            if let Some(event) = this.handle_event(event) {
+                return Poll::Ready(Some(Ok(event)));
+            }
+        }
+    }
+}
+
+impl Sink<TelnetFrame> for TelnetStream {
+    type Error = TokioIoError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.framed).poll_ready(cx)
     }
-}
\ No newline at end of file
+
+    fn start_send(self: Pin<&mut Self>, frame: TelnetFrame) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+
+        match frame {
+            TelnetFrame::Negotiate(verb, option) => {
+                let command = match verb {
+                    Verb::Will => this.negotiator.enable_local(option),
+                    Verb::Wont => this.negotiator.disable_local(option),
+                    Verb::Do => this.negotiator.enable_remote(option),
+                    Verb::Dont => this.negotiator.disable_remote(option),
+                };
+
+                match command {
+                    Some((verb, option)) => Pin::new(&mut this.framed).start_send(TelnetFrame::Negotiate(verb, option)),
+                    None => Ok(()),
+                }
+            },
+
+            TelnetFrame::Gmcp { .. } if !this.negotiator.local_enabled(TelnetOption::Gmcp) => Ok(()),
+
+            TelnetFrame::Msdp { variable, value } => {
+                match this.msdp.update(variable, value) {
+                    Some(payload) if this.negotiator.local_enabled(TelnetOption::Msdp) => {
+                        Pin::new(&mut this.framed).start_send(TelnetFrame::Subnegotiation(TelnetOption::Msdp, payload))
+                    },
+                    _ => Ok(()),
+                }
+            },
+
+            frame => Pin::new(&mut this.framed).start_send(frame),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_pending(cx))?;
+        ready!(Pin::new(&mut this.framed).poll_flush(cx))?;
+        this.needs_flush = false;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        // End the compressed stream cleanly so the client doesn't see it as corrupt.
+        if this.framed.codec().is_compressing() {
+            let request = this.negotiator.disable_local(TelnetOption::Mccp2);
+            this.queue_negotiation(request);
+        }
+
+        ready!(this.poll_pending(cx))?;
+        Pin::new(&mut this.framed).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt as _;
+    use tokio::io::AsyncWriteExt as _;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn every_msdp_variable_set_at_once_is_reported() {
+        let mut listener = TelnetListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (accepted, _addr) = listener.accept().await.unwrap();
+        let (mut stream, _) = accepted.handshake().await.unwrap();
+
+        // IAC SB MSDP VAR "CLIENT_NAME" VAL "test" VAR "CLIENT_VERSION" VAL "1" IAC SE
+        client.write_all(b"\xff\xfa\x45\x01CLIENT_NAME\x02test\x01CLIENT_VERSION\x021\xff\xf0").await.unwrap();
+
+        assert_eq!(stream.next().await.unwrap().unwrap(), TelnetEvent::Msdp { variable: "CLIENT_NAME".into(), value: "test".into() });
+        assert_eq!(stream.next().await.unwrap().unwrap(), TelnetEvent::Msdp { variable: "CLIENT_VERSION".into(), value: "1".into() });
+    }
+
+    #[tokio::test]
+    async fn replies_to_a_client_that_does_not_read_are_bounded() {
+        let mut listener = TelnetListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let (accepted, _addr) = listener.accept().await.unwrap();
+        let (mut stream, _) = accepted.handshake().await.unwrap();
+
+        // IAC AYT, far more than the socket buffers hold replies for.
+        tokio::spawn(async move {
+            let flood = [255, 246].repeat(4 * 1024 * 1024);
+            let _ = client.write_all(&flood).await;
+            client
+        });
+
+        while let Ok(Some(_)) = tokio::time::timeout(Duration::from_millis(500), stream.next()).await {}
+
+        assert!(stream.pending.len() <= MAX_PENDING);
+    }
+
+    #[tokio::test]
+    async fn missing_proxy_headers_time_out() {
+        let mut listener = TelnetListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        listener.set_trusted_proxies(vec![addr.ip()]);
+        listener.set_handshake_timeout(Duration::from_millis(100));
+
+        // Never sends its PROXY header.
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (accepted, _addr) = listener.accept().await.unwrap();
+
+        let err = accepted.handshake().await.err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,212 @@
+//! Limits on what clients may do, so one client cannot exhaust the server.
+//!
+//! Clients that break a limit are sent the `LimitExceeded` message and
+//! disconnected.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::{Error as TokioIoError, ErrorKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Connections open at once from one IP address.
+    pub connections_per_ip: usize,
+
+    /// Bytes in one line of input. Also limits subnegotiations.
+    pub line_length: usize,
+
+    /// Lines of input from one connection per second.
+    pub lines_per_second: u32,
+
+    /// Connections accepted per second, from all addresses together.
+    pub accepts_per_second: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            connections_per_ip: 10,
+            line_length: 4096,
+            lines_per_second: 50,
+            accepts_per_second: 20,
+        }
+    }
+}
+
+/// The limit a client broke. Displays as the message sent to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    ConnectionsPerIp,
+    AcceptRate,
+    LineLength,
+    LineRate,
+}
+
+impl LimitExceeded {
+    /// Get the limit out of an error returned by a `TelnetStream`, if that
+    /// is why it failed.
+    pub fn from_error(err: &TokioIoError) -> Option<Self> {
+        err.get_ref()?.downcast_ref::<Self>().copied()
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LimitExceeded::ConnectionsPerIp => "Too many connections from your address.",
+            LimitExceeded::AcceptRate => "Too many connections at once. Try again in a moment.",
+            LimitExceeded::LineLength => "Your input was too long.",
+            LimitExceeded::LineRate => "You sent input too quickly.",
+        })
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl From<LimitExceeded> for TokioIoError {
+    fn from(exceeded: LimitExceeded) -> Self {
+        TokioIoError::new(ErrorKind::InvalidData, exceeded)
+    }
+}
+
+/// Counts events in one second windows, such as lines of input against
+/// `Limits::lines_per_second`.
+#[derive(Debug)]
+pub struct Rate {
+    window_start: Instant,
+    count: u32,
+}
+
+impl Rate {
+    pub fn new() -> Self {
+        Self { window_start: Instant::now(), count: 0 }
+    }
+
+    /// Count an event, returning whether there have been more than `max`
+    /// this second.
+    pub fn exceeded(&mut self, max: u32) -> bool {
+        let now = Instant::now();
+
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.count = 0;
+        }
+
+        self.count += 1;
+        self.count > max
+    }
+}
+
+impl Default for Rate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    connections: HashMap<IpAddr, usize>,
+    accepts: Rate,
+}
+
+/// Enforces `Limits` on connections. Clones share their counts, so giving
+/// clones to several listeners limits them together.
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    limits: Limits,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Limiter {
+    pub fn new(limits: Limits) -> Self {
+        let shared = Shared { connections: HashMap::new(), accepts: Rate::new() };
+        Self { limits, shared: Arc::new(Mutex::new(shared)) }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Count an accepted connection against the accept rate.
+    pub fn accept(&self) -> Result<(), LimitExceeded> {
+        let mut shared = self.shared.lock().expect("Limiter lock poisoned.");
+
+        if shared.accepts.exceeded(self.limits.accepts_per_second) {
+            Err(LimitExceeded::AcceptRate)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Count a new connection from `ip`. It counts against the address until
+    /// the returned slot is dropped.
+    pub fn admit(&self, ip: IpAddr) -> Result<Slot, LimitExceeded> {
+        let mut shared = self.shared.lock().expect("Limiter lock poisoned.");
+        let connections = shared.connections.entry(ip).or_insert(0);
+
+        if *connections >= self.limits.connections_per_ip {
+            return Err(LimitExceeded::ConnectionsPerIp);
+        }
+
+        *connections += 1;
+        Ok(Slot { ip, shared: self.shared.clone() })
+    }
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Self::new(Limits::default())
+    }
+}
+
+/// An open connection counted against its IP address.
+#[derive(Debug)]
+pub struct Slot {
+    ip: IpAddr,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            if let Some(connections) = shared.connections.get_mut(&self.ip) {
+                *connections -= 1;
+
+                if *connections == 0 {
+                    shared.connections.remove(&self.ip);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn connections_per_ip_are_released_on_drop() {
+        let limiter = Limiter::new(Limits { connections_per_ip: 1, ..Limits::default() });
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        let slot = limiter.admit(ip).unwrap();
+        assert_eq!(limiter.admit(ip).unwrap_err(), LimitExceeded::ConnectionsPerIp);
+        assert!(limiter.admit(IpAddr::from([127, 0, 0, 2])).is_ok());
+
+        drop(slot);
+        assert!(limiter.admit(ip).is_ok());
+    }
+
+    #[test]
+    fn accepts_are_rate_limited() {
+        let limiter = Limiter::new(Limits { accepts_per_second: 2, ..Limits::default() });
+
+        assert_eq!(limiter.accept(), Ok(()));
+        assert_eq!(limiter.accept(), Ok(()));
+        assert_eq!(limiter.accept(), Err(LimitExceeded::AcceptRate));
+    }
+}
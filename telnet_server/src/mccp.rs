@@ -0,0 +1,159 @@
+//! Mud Client Compression Protocol.
+//!
+//! https://tintin.sourceforge.io/protocols/mccp/
+//!
+//! MCCP2 compresses everything the server sends after IAC SB MCCP2 IAC SE
+//! with zlib. The compressed stream ends with a zlib stream end, after which
+//! the connection is uncompressed again.
+//!
+//! MCCP3 is the same thing in the other direction, starting after the client
+//! sends IAC SB MCCP3 IAC SE.
+
+use bytes::{Buf, BytesMut};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use tokio::io::{Error as TokioIoError, ErrorKind};
+
+/// The zlib stream for outgoing data.
+pub(crate) struct Compressor {
+    compress: Compress,
+    buffer: Vec<u8>,
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        Self {
+            compress: Compress::new(Compression::default(), true),
+            buffer: Vec::with_capacity(256),
+        }
+    }
+
+    /// Compress `input` into `dst`, flushing so the client can decompress
+    /// everything sent so far.
+    pub fn compress(&mut self, input: &[u8], dst: &mut BytesMut) -> Result<(), TokioIoError> {
+        self.run(input, FlushCompress::Sync, dst)
+    }
+
+    /// End the zlib stream. Everything after this is uncompressed.
+    pub fn finish(mut self, dst: &mut BytesMut) -> Result<(), TokioIoError> {
+        self.run(&[], FlushCompress::Finish, dst)
+    }
+
+    fn run(&mut self, mut input: &[u8], flush: FlushCompress, dst: &mut BytesMut) -> Result<(), TokioIoError> {
+        loop {
+            self.buffer.clear();
+            self.buffer.reserve(input.len() + 64);
+
+            let before = self.compress.total_in();
+            let status = self.compress.compress_vec(input, &mut self.buffer, flush)
+            .map_err(TokioIoError::other)?;
+            input = &input[(self.compress.total_in() - before) as usize..];
+
+            dst.extend_from_slice(&self.buffer);
+
+            // zlib is done once it has taken all of the input and did not
+            // fill the output buffer.
+            let buffer_full = self.buffer.len() == self.buffer.capacity();
+
+            match status {
+                Status::StreamEnd => return Ok(()),
+                _ if input.is_empty() && !buffer_full && flush != FlushCompress::Finish => return Ok(()),
+                _ => {},
+            }
+        }
+    }
+}
+
+/// The zlib stream for incoming data.
+pub(crate) struct Decompressor {
+    decompress: Decompress,
+    buffer: Vec<u8>,
+    more: bool,
+}
+
+impl Decompressor {
+    pub fn new() -> Self {
+        Self {
+            decompress: Decompress::new(true),
+            buffer: Vec::with_capacity(256),
+            more: false,
+        }
+    }
+
+    /// Inflate `src` into `dst`, removing what was used from `src`. Stops
+    /// after inflating `max` bytes, leaving the rest for the next call.
+    ///
+    /// Returns whether the zlib stream ended. If it did, whatever is left
+    /// in `src` is uncompressed.
+    pub fn decompress(&mut self, src: &mut BytesMut, dst: &mut BytesMut, max: usize) -> Result<bool, TokioIoError> {
+        let mut inflated = 0;
+        self.more = false;
+
+        while inflated < max {
+            self.buffer.resize((max - inflated).min(src.len() * 4 + 64), 0);
+
+            let (before_in, before_out) = (self.decompress.total_in(), self.decompress.total_out());
+            let status = self.decompress.decompress(src, &mut self.buffer, FlushDecompress::None)
+            .map_err(|err| TokioIoError::new(ErrorKind::InvalidData, format!("corrupt MCCP3 compressed input: {}", err)))?;
+            src.advance((self.decompress.total_in() - before_in) as usize);
+
+            let produced = (self.decompress.total_out() - before_out) as usize;
+            dst.extend_from_slice(&self.buffer[..produced]);
+            inflated += produced;
+
+            let buffer_full = produced == self.buffer.len();
+
+            match status {
+                Status::StreamEnd => return Ok(true),
+                _ if src.is_empty() && !buffer_full => return Ok(false),
+                // zlib can't make progress without more input.
+                Status::BufError if !buffer_full => return Ok(false),
+                _ => {},
+            }
+        }
+
+        self.more = true;
+        Ok(false)
+    }
+
+    /// Whether the last call stopped at `max`, so that there may be more
+    /// to inflate even without more input.
+    pub fn has_more(&self) -> bool {
+        self.more
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compressed_output_round_trips() {
+        let mut compressor = Compressor::new();
+        let mut dst = BytesMut::new();
+
+        compressor.compress(b"You are in a generic room.\r\n", &mut dst).unwrap();
+        compressor.compress(b"Exits: forward\r\n", &mut dst).unwrap();
+        compressor.finish(&mut dst).unwrap();
+
+        let mut decompress = Decompress::new(true);
+        let mut out = Vec::with_capacity(256);
+        let status = decompress.decompress_vec(&dst, &mut out, FlushDecompress::Finish).unwrap();
+
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(out, b"You are in a generic room.\r\nExits: forward\r\n".to_vec());
+    }
+
+    #[test]
+    fn each_write_is_decompressable_on_its_own() {
+        let mut compressor = Compressor::new();
+        let mut dst = BytesMut::new();
+
+        compressor.compress(b"> ", &mut dst).unwrap();
+
+        let mut decompress = Decompress::new(true);
+        let mut out = Vec::with_capacity(16);
+        decompress.decompress_vec(&dst, &mut out, FlushDecompress::Sync).unwrap();
+
+        assert_eq!(out, b"> ".to_vec());
+    }
+}
@@ -0,0 +1,433 @@
+//! MUD Server Data Protocol.
+//!
+//! https://tintin.sourceforge.io/protocols/msdp/
+//!
+//! The server publishes named variables. The client can ask for them once
+//! with SEND or ask to be sent every change with REPORT.
+
+use std::collections::{HashMap, HashSet};
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+const VAR: u8 = 1;
+const VAL: u8 = 2;
+const TABLE_OPEN: u8 = 3;
+const TABLE_CLOSE: u8 = 4;
+const ARRAY_OPEN: u8 = 5;
+const ARRAY_CLOSE: u8 = 6;
+
+const COMMANDS: [&str; 5] = ["LIST", "REPORT", "RESET", "SEND", "UNREPORT"];
+const LISTS: [&str; 5] = ["COMMANDS", "LISTS", "REPORTABLE_VARIABLES", "REPORTED_VARIABLES", "SENDABLE_VARIABLES"];
+
+/// The most tables and arrays that may be nested in each other. Deeper
+/// payloads are ignored so that parsing them can't overflow the stack.
+const MAX_NESTING: usize = 8;
+
+/// The value of an MSDP variable.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MsdpValue {
+    String(String),
+    Array(Vec<MsdpValue>),
+    Table(Vec<(String, MsdpValue)>),
+}
+
+impl MsdpValue {
+    /// The strings in this value. Commands take either one value or an array.
+    fn strings(&self) -> Vec<&str> {
+        match self {
+            MsdpValue::String(string) => vec![&**string],
+            MsdpValue::Array(values) => values.iter().flat_map(MsdpValue::strings).collect(),
+            MsdpValue::Table(_) => vec![],
+        }
+    }
+
+    fn encode(&self, dst: &mut BytesMut) {
+        match self {
+            MsdpValue::String(string) => dst.extend_from_slice(string.as_bytes()),
+
+            MsdpValue::Array(values) => {
+                dst.put_u8(ARRAY_OPEN);
+
+                for value in values {
+                    dst.put_u8(VAL);
+                    value.encode(dst);
+                }
+
+                dst.put_u8(ARRAY_CLOSE);
+            },
+
+            MsdpValue::Table(entries) => {
+                dst.put_u8(TABLE_OPEN);
+
+                for (variable, value) in entries {
+                    encode_variable(variable, value, dst);
+                }
+
+                dst.put_u8(TABLE_CLOSE);
+            },
+        }
+    }
+}
+
+impl From<String> for MsdpValue {
+    fn from(string: String) -> Self {
+        MsdpValue::String(string)
+    }
+}
+
+impl From<&str> for MsdpValue {
+    fn from(string: &str) -> Self {
+        MsdpValue::String(string.to_string())
+    }
+}
+
+fn encode_variable(variable: &str, value: &MsdpValue, dst: &mut BytesMut) {
+    dst.put_u8(VAR);
+    dst.extend_from_slice(variable.as_bytes());
+    dst.put_u8(VAL);
+    value.encode(dst);
+}
+
+/// Build the payload of an MSDP subnegotiation for one variable.
+pub(crate) fn encode(variable: &str, value: &MsdpValue) -> Bytes {
+    let mut dst = BytesMut::new();
+    encode_variable(variable, value, &mut dst);
+    dst.freeze()
+}
+
+fn names_value<'a>(names: impl Iterator<Item = &'a str>) -> MsdpValue {
+    let mut names = names.collect::<Vec<_>>();
+    names.sort_unstable();
+    MsdpValue::Array(names.into_iter().map(MsdpValue::from).collect())
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// Tables and arrays the parser is inside of.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    /// Text up to the next MSDP control byte.
+    fn text(&mut self) -> String {
+        let start = self.position;
+
+        while let Some(byte) = self.peek() {
+            if (VAR..=ARRAY_CLOSE).contains(&byte) {
+                break;
+            }
+
+            self.position += 1;
+        }
+
+        String::from_utf8_lossy(&self.bytes[start..self.position]).into_owned()
+    }
+
+    /// Returns `None` if tables and arrays are nested too deeply.
+    fn value(&mut self) -> Option<MsdpValue> {
+        let value = match self.peek() {
+            Some(TABLE_OPEN) => {
+                self.open()?;
+                let mut entries = vec![];
+
+                while let Some(VAR) = self.peek() {
+                    entries.push(self.variable()?);
+                }
+
+                self.close(TABLE_CLOSE);
+                MsdpValue::Table(entries)
+            },
+
+            Some(ARRAY_OPEN) => {
+                self.open()?;
+                let mut values = vec![];
+
+                while let Some(VAL) = self.peek() {
+                    self.position += 1;
+                    values.push(self.value()?);
+                }
+
+                self.close(ARRAY_CLOSE);
+                MsdpValue::Array(values)
+            },
+
+            _ => MsdpValue::String(self.text()),
+        };
+
+        Some(value)
+    }
+
+    /// Enter a table or array.
+    fn open(&mut self) -> Option<()> {
+        if self.depth == MAX_NESTING {
+            return None;
+        }
+
+        self.position += 1;
+        self.depth += 1;
+        Some(())
+    }
+
+    /// Leave a table or array, skipping its closing byte if it has one.
+    fn close(&mut self, close: u8) {
+        if self.peek() == Some(close) {
+            self.position += 1;
+        }
+
+        self.depth -= 1;
+    }
+
+    /// VAR <name> followed by one or more VAL <value>. Several values are
+    /// collected into an array.
+    fn variable(&mut self) -> Option<(String, MsdpValue)> {
+        self.position += 1;
+        let name = self.text();
+        let mut values = vec![];
+
+        while let Some(VAL) = self.peek() {
+            self.position += 1;
+            values.push(self.value()?);
+        }
+
+        let value = match values.len() {
+            0 => MsdpValue::String(String::new()),
+            1 => values.pop().expect("Checked length is 1."),
+            _ => MsdpValue::Array(values),
+        };
+
+        Some((name, value))
+    }
+}
+
+/// Parse the payload of an MSDP subnegotiation into its variables. Returns
+/// `None` if tables and arrays are nested more than `MAX_NESTING` deep.
+pub(crate) fn parse(payload: &[u8]) -> Option<Vec<(String, MsdpValue)>> {
+    let mut parser = Parser { bytes: payload, position: 0, depth: 0 };
+    let mut variables = vec![];
+
+    while parser.position < payload.len() {
+        match parser.peek() {
+            Some(VAR) => variables.push(parser.variable()?),
+            // Skip anything that isn't a variable.
+            _ => parser.position += 1,
+        }
+    }
+
+    Some(variables)
+}
+
+/// The variables published for a connection and which ones are reported.
+#[derive(Default)]
+pub(crate) struct Msdp {
+    values: HashMap<String, MsdpValue>,
+    reportable: HashSet<String>,
+    reported: HashSet<String>,
+}
+
+/// What to do about an MSDP subnegotiation from the client.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Received {
+    /// Payloads of MSDP subnegotiations to send back.
+    pub replies: Vec<Bytes>,
+
+    /// Variables the client set that are not commands.
+    pub variables: Vec<(String, MsdpValue)>,
+}
+
+impl Msdp {
+    pub fn receive(&mut self, payload: &[u8]) -> Received {
+        let mut received = Received::default();
+
+        // Payloads nested too deeply are ignored.
+        for (variable, value) in parse(payload).unwrap_or_default() {
+            match &*variable {
+                "LIST" => {
+                    for list in value.strings() {
+                        if let Some(names) = self.list(list) {
+                            received.replies.push(encode(list, &names));
+                        }
+                    }
+                },
+
+                // Only reportable variables are remembered, so that a client
+                // can't grow the reported set without bound.
+                "REPORT" => {
+                    for name in value.strings() {
+                        if !self.is_reportable(name) {
+                            continue;
+                        }
+
+                        self.reported.insert(name.to_string());
+
+                        if let Some(value) = self.values.get(name) {
+                            received.replies.push(encode(name, value));
+                        }
+                    }
+                },
+
+                "UNREPORT" => {
+                    for name in value.strings() {
+                        self.reported.remove(name);
+                    }
+                },
+
+                "RESET" => {
+                    if value.strings().contains(&"REPORTED_VARIABLES") || value.strings().contains(&"REPORTABLE_VARIABLES") {
+                        self.reported.clear();
+                    }
+                },
+
+                "SEND" => {
+                    for name in value.strings() {
+                        if let Some(value) = self.values.get(name) {
+                            received.replies.push(encode(name, value));
+                        }
+                    }
+                },
+
+                _ => received.variables.push((variable, value)),
+            }
+        }
+
+        received
+    }
+
+    fn list(&self, list: &str) -> Option<MsdpValue> {
+        Some(match list {
+            "COMMANDS" => names_value(COMMANDS.iter().copied()),
+            "LISTS" => names_value(LISTS.iter().copied()),
+            "REPORTABLE_VARIABLES" => {
+                let unpublished = self.reportable.iter().filter(|name| !self.values.contains_key(*name));
+                names_value(self.values.keys().chain(unpublished).map(|name| &**name))
+            },
+            "SENDABLE_VARIABLES" => names_value(self.values.keys().map(|name| &**name)),
+            "REPORTED_VARIABLES" => names_value(self.reported.iter().map(|name| &**name)),
+            _ => return None,
+        })
+    }
+
+    /// Variables that have been published or that may be published later.
+    fn is_reportable(&self, name: &str) -> bool {
+        self.values.contains_key(name) || self.reportable.contains(name)
+    }
+
+    /// Declare variables that may be reported before they are published.
+    pub fn set_reportable(&mut self, names: impl IntoIterator<Item = String>) {
+        self.reportable = names.into_iter().collect();
+    }
+
+    /// The variables the client asked to have reported.
+    pub fn reported(&self) -> Vec<String> {
+        self.reported.iter().cloned().collect()
+    }
+
+    pub fn set_reported(&mut self, reported: Vec<String>) {
+        self.reported = reported.into_iter().collect();
+    }
+
+    /// Publish a new value for a variable. Returns the payload to send if
+    /// the client asked for the variable to be reported and it changed.
+    pub fn update(&mut self, variable: String, value: MsdpValue) -> Option<Bytes> {
+        if self.values.get(&variable) == Some(&value) {
+            return None;
+        }
+
+        let payload = if self.reported.contains(&variable) {
+            Some(encode(&variable, &value))
+        } else {
+            None
+        };
+
+        self.values.insert(variable, value);
+        payload
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_nested_values() {
+        let payload = b"\x01ROOM\x02\x03\x01NAME\x02Hall\x01EXITS\x02\x05\x02back\x02forward\x06\x04";
+
+        assert_eq!(parse(payload).unwrap(), vec![
+            ("ROOM".to_string(), MsdpValue::Table(vec![
+                ("NAME".to_string(), "Hall".into()),
+                ("EXITS".to_string(), MsdpValue::Array(vec!["back".into(), "forward".into()])),
+            ])),
+        ]);
+    }
+
+    #[test]
+    fn deeply_nested_values_are_rejected() {
+        let mut payload = b"\x01X\x02".to_vec();
+        for _ in 0..100_000 {
+            payload.extend_from_slice(&[ARRAY_OPEN, VAL]);
+        }
+
+        assert_eq!(parse(&payload), None);
+        assert!(parse(b"\x01X\x02\x05\x02\x05\x02a\x06\x06").is_some());
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        let value = MsdpValue::Array(vec!["a".into(), MsdpValue::Table(vec![("b".to_string(), "c".into())])]);
+
+        assert_eq!(parse(&encode("X", &value)).unwrap(), vec![("X".to_string(), value)]);
+    }
+
+    #[test]
+    fn reported_variables_are_sent_on_change() {
+        let mut msdp = Msdp::default();
+        msdp.update("ROOM_NAME".to_string(), "Hall".into());
+
+        let received = msdp.receive(b"\x01REPORT\x02ROOM_NAME");
+        assert_eq!(received.replies, vec![encode("ROOM_NAME", &"Hall".into())]);
+
+        assert_eq!(msdp.update("ROOM_NAME".to_string(), "Hall".into()), None);
+        assert_eq!(msdp.update("ROOM_NAME".to_string(), "Garden".into()), Some(encode("ROOM_NAME", &"Garden".into())));
+
+        msdp.receive(b"\x01UNREPORT\x02ROOM_NAME");
+        assert_eq!(msdp.update("ROOM_NAME".to_string(), "Hall".into()), None);
+    }
+
+    #[test]
+    fn unknown_variables_are_not_reported() {
+        let mut msdp = Msdp::default();
+
+        let received = msdp.receive(b"\x01REPORT\x02UNKNOWN");
+        assert_eq!(received.replies, Vec::<Bytes>::new());
+        assert!(msdp.reported().is_empty());
+
+        assert_eq!(msdp.update("UNKNOWN".to_string(), "Value".into()), None);
+    }
+
+    #[test]
+    fn declared_variables_are_reported_once_published() {
+        let mut msdp = Msdp::default();
+        msdp.set_reportable(vec!["CRAFT_STATE".to_string()]);
+
+        let received = msdp.receive(b"\x01REPORT\x02CRAFT_STATE");
+        assert_eq!(received.replies, Vec::<Bytes>::new());
+
+        assert_eq!(msdp.update("CRAFT_STATE".to_string(), "done".into()), Some(encode("CRAFT_STATE", &"done".into())));
+    }
+
+    #[test]
+    fn list_reportable_variables() {
+        let mut msdp = Msdp::default();
+        msdp.update("ROOM_NAME".to_string(), "Hall".into());
+
+        let received = msdp.receive(b"\x01LIST\x02REPORTABLE_VARIABLES");
+
+        assert_eq!(received.replies, vec![
+            encode("REPORTABLE_VARIABLES", &MsdpValue::Array(vec!["ROOM_NAME".into()])),
+        ]);
+    }
+}
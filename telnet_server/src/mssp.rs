@@ -0,0 +1,69 @@
+//! Mud Server Status Protocol.
+//!
+//! https://tintin.sourceforge.io/protocols/mssp/
+//!
+//! MUD listing sites read the server's status, such as its name and player
+//! count, as IAC SB MSSP MSSP_VAR <name> MSSP_VAL <value> ... IAC SE. Those
+//! that don't negotiate options send the line `MSSP-REQUEST` instead and get
+//! the same variables as plain text.
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+const MSSP_VAR: u8 = 1;
+const MSSP_VAL: u8 = 2;
+
+/// The line asking for the plain text status.
+pub(crate) const REQUEST: &str = "MSSP-REQUEST";
+
+/// Variables and their values. A variable listed more than once, such as
+/// PORT, has several values.
+pub type MsspVariables = Vec<(String, String)>;
+
+/// Build the payload of an MSSP subnegotiation.
+pub(crate) fn encode(variables: &[(String, String)]) -> Bytes {
+    let mut payload = BytesMut::new();
+    let mut previous: Option<&str> = None;
+
+    for (variable, value) in variables {
+        // Further values of the same variable follow the first value.
+        if previous != Some(variable) {
+            payload.put_u8(MSSP_VAR);
+            payload.extend_from_slice(variable.as_bytes());
+            previous = Some(variable);
+        }
+
+        payload.put_u8(MSSP_VAL);
+        payload.extend_from_slice(value.as_bytes());
+    }
+
+    payload.freeze()
+}
+
+/// The reply to `MSSP-REQUEST`, with each value on its own line.
+pub(crate) fn plain_text(variables: &[(String, String)]) -> Bytes {
+    let mut text = String::from("\r\nMSSP-REPLY-START\r\n");
+
+    for (variable, value) in variables {
+        text.push_str(&format!("{}\t{}\r\n", variable, value));
+    }
+
+    text.push_str("MSSP-REPLY-END\r\n");
+    text.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repeated_variables_have_several_values() {
+        let variables = vec![
+            ("NAME".to_string(), "CraftMud".to_string()),
+            ("PORT".to_string(), "23".to_string()),
+            ("PORT".to_string(), "4000".to_string()),
+        ];
+
+        assert_eq!(&encode(&variables)[..], &b"\x01NAME\x02CraftMud\x01PORT\x0223\x024000"[..]);
+        assert_eq!(&plain_text(&variables)[..], &b"\r\nMSSP-REPLY-START\r\nNAME\tCraftMud\r\nPORT\t23\r\nPORT\t4000\r\nMSSP-REPLY-END\r\n"[..]);
+    }
+}
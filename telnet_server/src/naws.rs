@@ -0,0 +1,32 @@
+//! Negotiate About Window Size.
+//!
+//! https://tools.ietf.org/html/rfc1073
+//!
+//! Once the client agrees to WILL NAWS, it sends its window size right away
+//! and again every time the window is resized.
+
+/// Parse the payload of IAC SB NAWS <payload> IAC SE into width and height.
+///
+/// A width or height of 0 means the client does not know that dimension.
+pub(crate) fn parse(payload: &[u8]) -> Option<(u16, u16)> {
+    match *payload {
+        [w1, w0, h1, h0] => Some((u16::from_be_bytes([w1, w0]), u16::from_be_bytes([h1, h0]))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_window_size() {
+        assert_eq!(parse(&[0, 80, 0, 24]), Some((80, 24)));
+        assert_eq!(parse(&[1, 0, 0, 255]), Some((256, 255)));
+    }
+
+    #[test]
+    fn wrong_length_is_rejected() {
+        assert_eq!(parse(&[0, 80, 0]), None);
+    }
+}
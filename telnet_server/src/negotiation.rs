@@ -0,0 +1,324 @@
+//! Option negotiation following the "Q Method" of RFC 1143.
+//!
+//! https://tools.ietf.org/html/rfc1143
+//!
+//! Each option has two independent sides. The local side is whether this
+//! server performs the option (we send WILL/WONT, they send DO/DONT) and the
+//! remote side is whether the client performs the option (we send DO/DONT,
+//! they send WILL/WONT). The Q Method remembers which requests are in flight
+//! so that neither party can cause a negotiation loop.
+
+use crate::options::TelnetOption;
+
+/// The four negotiation commands.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Verb {
+    Will,
+    Wont,
+    Do,
+    Dont,
+}
+
+/// Which party performs an option.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Party {
+    /// This server.
+    Local,
+
+    /// The connected client.
+    Remote,
+}
+
+/// An option being turned on or off for one of the parties.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct OptionChange {
+    pub option: TelnetOption,
+    pub party: Party,
+    pub enabled: bool,
+}
+
+/// The result of receiving a negotiation command from the client.
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct Outcome {
+    /// Negotiation command to send back to the client.
+    pub reply: Option<(Verb, TelnetOption)>,
+
+    /// Whether the option actually changed state.
+    pub change: Option<OptionChange>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum QState {
+    No,
+    Yes,
+    /// Waiting on the other party to agree to disable the option. When
+    /// `opposite` is set, the option should be re-enabled once they do.
+    WantNo { opposite: bool },
+    /// Waiting on the other party to agree to enable the option. When
+    /// `opposite` is set, the option should be disabled once they do.
+    WantYes { opposite: bool },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OptionState {
+    local: QState,
+    remote: QState,
+    local_supported: bool,
+    remote_supported: bool,
+}
+
+impl Default for OptionState {
+    fn default() -> Self {
+        Self {
+            local: QState::No,
+            remote: QState::No,
+            local_supported: false,
+            remote_supported: false,
+        }
+    }
+}
+
+/// Per-connection negotiation state for every option.
+///
+/// Options are refused unless they have been marked as supported with
+/// `support_local` or `support_remote`.
+pub struct Negotiator {
+    options: Vec<OptionState>,
+}
+
+impl Negotiator {
+    pub fn new() -> Self {
+        Self {
+            options: vec![OptionState::default(); 256],
+        }
+    }
+
+    fn state(&self, option: TelnetOption) -> &OptionState {
+        &self.options[u8::from(option) as usize]
+    }
+
+    fn state_mut(&mut self, option: TelnetOption) -> &mut OptionState {
+        &mut self.options[u8::from(option) as usize]
+    }
+
+    /// Allow the client to ask this server to perform the option.
+    pub fn support_local(&mut self, option: TelnetOption) {
+        self.state_mut(option).local_supported = true;
+    }
+
+    /// Allow the client to offer to perform the option.
+    pub fn support_remote(&mut self, option: TelnetOption) {
+        self.state_mut(option).remote_supported = true;
+    }
+
+    pub fn local_enabled(&self, option: TelnetOption) -> bool {
+        self.state(option).local == QState::Yes
+    }
+
+    pub fn remote_enabled(&self, option: TelnetOption) -> bool {
+        self.state(option).remote == QState::Yes
+    }
+
+    /// The options enabled for `party`.
+    pub(crate) fn enabled(&self, party: Party) -> Vec<TelnetOption> {
+        (0..=255u8)
+        .map(TelnetOption::from)
+        .filter(|&option| match party {
+            Party::Local => self.local_enabled(option),
+            Party::Remote => self.remote_enabled(option),
+        })
+        .collect()
+    }
+
+    /// Enable exactly these options, as negotiated by an earlier connection.
+    /// Requests in flight are forgotten.
+    pub(crate) fn restore(&mut self, local: &[TelnetOption], remote: &[TelnetOption]) {
+        for (index, state) in self.options.iter_mut().enumerate() {
+            let option = TelnetOption::from(index as u8);
+            state.local = if local.contains(&option) { QState::Yes } else { QState::No };
+            state.remote = if remote.contains(&option) { QState::Yes } else { QState::No };
+        }
+    }
+
+    /// Handle a negotiation command sent by the client.
+    pub fn receive(&mut self, verb: Verb, option: TelnetOption) -> Outcome {
+        let state = self.state_mut(option);
+
+        match verb {
+            Verb::Will => {
+                let supported = state.remote_supported;
+                receive_enable(&mut state.remote, supported, option, Party::Remote, Verb::Do, Verb::Dont)
+            },
+            Verb::Wont => receive_disable(&mut state.remote, option, Party::Remote, Verb::Do, Verb::Dont),
+            Verb::Do => {
+                let supported = state.local_supported;
+                receive_enable(&mut state.local, supported, option, Party::Local, Verb::Will, Verb::Wont)
+            },
+            Verb::Dont => receive_disable(&mut state.local, option, Party::Local, Verb::Will, Verb::Wont),
+        }
+    }
+
+    /// Ask to start performing the option. Returns the command to send, if any.
+    pub fn enable_local(&mut self, option: TelnetOption) -> Option<(Verb, TelnetOption)> {
+        request_enable(&mut self.state_mut(option).local).map(|()| (Verb::Will, option))
+    }
+
+    /// Ask to stop performing the option. Returns the command to send, if any.
+    pub fn disable_local(&mut self, option: TelnetOption) -> Option<(Verb, TelnetOption)> {
+        request_disable(&mut self.state_mut(option).local).map(|()| (Verb::Wont, option))
+    }
+
+    /// Ask the client to start performing the option. Returns the command to send, if any.
+    pub fn enable_remote(&mut self, option: TelnetOption) -> Option<(Verb, TelnetOption)> {
+        request_enable(&mut self.state_mut(option).remote).map(|()| (Verb::Do, option))
+    }
+
+    /// Ask the client to stop performing the option. Returns the command to send, if any.
+    pub fn disable_remote(&mut self, option: TelnetOption) -> Option<(Verb, TelnetOption)> {
+        request_disable(&mut self.state_mut(option).remote).map(|()| (Verb::Dont, option))
+    }
+}
+
+impl Default for Negotiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn receive_enable(q: &mut QState, supported: bool, option: TelnetOption, party: Party, agree: Verb, refuse: Verb) -> Outcome {
+    let enabled = OptionChange { option, party, enabled: true };
+    let disabled = OptionChange { option, party, enabled: false };
+
+    match *q {
+        QState::No if supported => {
+            *q = QState::Yes;
+            Outcome { reply: Some((agree, option)), change: Some(enabled) }
+        },
+        QState::No => Outcome { reply: Some((refuse, option)), change: None },
+        QState::Yes => Outcome::default(),
+        // The other party answered our disable request with an enable. RFC 1143
+        // says to treat this as the option being disabled.
+        QState::WantNo { opposite: false } => {
+            *q = QState::No;
+            Outcome { reply: None, change: Some(disabled) }
+        },
+        QState::WantNo { opposite: true } => {
+            *q = QState::Yes;
+            Outcome::default()
+        },
+        QState::WantYes { opposite: false } => {
+            *q = QState::Yes;
+            Outcome { reply: None, change: Some(enabled) }
+        },
+        QState::WantYes { opposite: true } => {
+            *q = QState::WantNo { opposite: false };
+            Outcome { reply: Some((refuse, option)), change: Some(enabled) }
+        },
+    }
+}
+
+fn receive_disable(q: &mut QState, option: TelnetOption, party: Party, agree: Verb, refuse: Verb) -> Outcome {
+    let disabled = OptionChange { option, party, enabled: false };
+
+    match *q {
+        QState::No => Outcome::default(),
+        QState::Yes => {
+            *q = QState::No;
+            Outcome { reply: Some((refuse, option)), change: Some(disabled) }
+        },
+        QState::WantNo { opposite: false } => {
+            *q = QState::No;
+            Outcome { reply: None, change: Some(disabled) }
+        },
+        QState::WantNo { opposite: true } => {
+            *q = QState::WantYes { opposite: false };
+            Outcome { reply: Some((agree, option)), change: Some(disabled) }
+        },
+        QState::WantYes { .. } => {
+            *q = QState::No;
+            Outcome::default()
+        },
+    }
+}
+
+fn request_enable(q: &mut QState) -> Option<()> {
+    match *q {
+        QState::No => {
+            *q = QState::WantYes { opposite: false };
+            Some(())
+        },
+        QState::WantNo { opposite: false } => {
+            *q = QState::WantNo { opposite: true };
+            None
+        },
+        QState::WantYes { opposite: true } => {
+            *q = QState::WantYes { opposite: false };
+            None
+        },
+        QState::Yes | QState::WantNo { opposite: true } | QState::WantYes { opposite: false } => None,
+    }
+}
+
+fn request_disable(q: &mut QState) -> Option<()> {
+    match *q {
+        QState::Yes => {
+            *q = QState::WantNo { opposite: false };
+            Some(())
+        },
+        QState::WantYes { opposite: false } => {
+            *q = QState::WantYes { opposite: true };
+            None
+        },
+        QState::WantNo { opposite: true } => {
+            *q = QState::WantNo { opposite: false };
+            None
+        },
+        QState::No | QState::WantNo { opposite: false } | QState::WantYes { opposite: true } => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unsupported_options_are_refused() {
+        let mut negotiator = Negotiator::new();
+
+        assert_eq!(negotiator.receive(Verb::Do, TelnetOption::Echo).reply, Some((Verb::Wont, TelnetOption::Echo)));
+        assert_eq!(negotiator.receive(Verb::Will, TelnetOption::Echo).reply, Some((Verb::Dont, TelnetOption::Echo)));
+        assert!(!negotiator.local_enabled(TelnetOption::Echo));
+        assert!(!negotiator.remote_enabled(TelnetOption::Echo));
+    }
+
+    #[test]
+    fn acknowledgements_are_not_answered() {
+        let mut negotiator = Negotiator::new();
+        negotiator.support_local(TelnetOption::Echo);
+
+        assert_eq!(negotiator.enable_local(TelnetOption::Echo), Some((Verb::Will, TelnetOption::Echo)));
+        assert_eq!(negotiator.enable_local(TelnetOption::Echo), None);
+
+        let outcome = negotiator.receive(Verb::Do, TelnetOption::Echo);
+        assert_eq!(outcome.reply, None);
+        assert_eq!(outcome.change, Some(OptionChange { option: TelnetOption::Echo, party: Party::Local, enabled: true }));
+
+        // A repeated DO must not cause another WILL.
+        assert_eq!(negotiator.receive(Verb::Do, TelnetOption::Echo), Outcome::default());
+    }
+
+    #[test]
+    fn queued_opposite_request_is_sent_after_answer() {
+        let mut negotiator = Negotiator::new();
+
+        assert_eq!(negotiator.enable_remote(TelnetOption::SuppressGoAhead), Some((Verb::Do, TelnetOption::SuppressGoAhead)));
+        assert_eq!(negotiator.disable_remote(TelnetOption::SuppressGoAhead), None);
+
+        let outcome = negotiator.receive(Verb::Will, TelnetOption::SuppressGoAhead);
+        assert_eq!(outcome.reply, Some((Verb::Dont, TelnetOption::SuppressGoAhead)));
+
+        let outcome = negotiator.receive(Verb::Wont, TelnetOption::SuppressGoAhead);
+        assert_eq!(outcome.reply, None);
+        assert!(!negotiator.remote_enabled(TelnetOption::SuppressGoAhead));
+    }
+}
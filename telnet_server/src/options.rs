@@ -0,0 +1,91 @@
+//! The options that can be negotiated with WILL, WONT, DO, and DONT.
+//!
+//! Option numbers are assigned by IANA:
+//! https://www.iana.org/assignments/telnet-options/telnet-options.xhtml
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum TelnetOption {
+    /// Binary Transmission. https://tools.ietf.org/html/rfc856
+    Binary,
+
+    /// Echo. https://tools.ietf.org/html/rfc857
+    Echo,
+
+    /// Suppress Go Ahead. https://tools.ietf.org/html/rfc858
+    SuppressGoAhead,
+
+    /// Terminal Type. https://tools.ietf.org/html/rfc1091
+    TerminalType,
+
+    /// End of Record. https://tools.ietf.org/html/rfc885
+    EndOfRecord,
+
+    /// Negotiate About Window Size. https://tools.ietf.org/html/rfc1073
+    Naws,
+
+    /// Character set negotiation. https://tools.ietf.org/html/rfc2066
+    Charset,
+
+    /// MUD Server Data Protocol. https://tintin.sourceforge.io/protocols/msdp/
+    Msdp,
+
+    /// Mud Server Status Protocol. https://tintin.sourceforge.io/protocols/mssp/
+    Mssp,
+
+    /// Mud Client Compression Protocol v2. https://tintin.sourceforge.io/protocols/mccp/
+    Mccp2,
+
+    /// Mud Client Compression Protocol v3. https://tintin.sourceforge.io/protocols/mccp/
+    Mccp3,
+
+    /// MUD eXtension Protocol. https://www.zuggsoft.com/zmud/mxp.htm
+    Mxp,
+
+    /// Generic MUD Communication Protocol. https://www.gammon.com.au/gmcp
+    Gmcp,
+
+    /// Any option this server does not know about.
+    Unknown(u8),
+}
+
+impl From<u8> for TelnetOption {
+    fn from(byte: u8) -> Self {
+        match byte {
+            0 => Self::Binary,
+            1 => Self::Echo,
+            3 => Self::SuppressGoAhead,
+            24 => Self::TerminalType,
+            25 => Self::EndOfRecord,
+            31 => Self::Naws,
+            42 => Self::Charset,
+            69 => Self::Msdp,
+            70 => Self::Mssp,
+            86 => Self::Mccp2,
+            87 => Self::Mccp3,
+            91 => Self::Mxp,
+            201 => Self::Gmcp,
+            byte => Self::Unknown(byte),
+        }
+    }
+}
+
+impl From<TelnetOption> for u8 {
+    fn from(option: TelnetOption) -> Self {
+        match option {
+            TelnetOption::Binary => 0,
+            TelnetOption::Echo => 1,
+            TelnetOption::SuppressGoAhead => 3,
+            TelnetOption::TerminalType => 24,
+            TelnetOption::EndOfRecord => 25,
+            TelnetOption::Naws => 31,
+            TelnetOption::Charset => 42,
+            TelnetOption::Msdp => 69,
+            TelnetOption::Mssp => 70,
+            TelnetOption::Mccp2 => 86,
+            TelnetOption::Mccp3 => 87,
+            TelnetOption::Mxp => 91,
+            TelnetOption::Gmcp => 201,
+            TelnetOption::Unknown(byte) => byte,
+        }
+    }
+}
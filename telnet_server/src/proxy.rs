@@ -0,0 +1,139 @@
+//! The PROXY protocol, which load balancers use to pass on the address of the
+//! client they accepted a connection from.
+//!
+//! https://www.haproxy.org/download/2.2/doc/proxy-protocol.txt
+//!
+//! The proxy sends a header before any of the client's data. Version 1 is a
+//! line of text like `PROXY TCP4 <source> <destination> <source port>
+//! <destination port>`. Version 2 is binary.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt as _, Error as TokioIoError, ErrorKind};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The longest version 1 header, including the CR LF.
+const V1_MAX_LENGTH: usize = 107;
+
+const V2_COMMAND_PROXY: u8 = 0x1;
+const V2_FAMILY_INET: u8 = 0x1;
+const V2_FAMILY_INET6: u8 = 0x2;
+
+fn invalid(message: &str) -> TokioIoError {
+    TokioIoError::new(ErrorKind::InvalidData, format!("invalid PROXY header: {}", message))
+}
+
+/// Read the PROXY header from the start of a connection, returning the
+/// client's address. It is `None` when the proxy does not know it or made
+/// the connection itself, such as for health checks.
+///
+/// Only the header is read, so the client's data is left for TELNET.
+pub(crate) async fn read_header<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<SocketAddr>, TokioIoError> {
+    // Both versions are at least this long.
+    let mut start = [0; 12];
+    reader.read_exact(&mut start).await?;
+
+    if &start == V2_SIGNATURE {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header).await?;
+
+        let length = u16::from_be_bytes([header[2], header[3]]) as usize;
+        let mut addresses = vec![0; length];
+        reader.read_exact(&mut addresses).await?;
+
+        parse_v2(header[0], header[1], &addresses)
+    } else if start.starts_with(V1_PREFIX) {
+        let mut line = start.to_vec();
+
+        while !line.ends_with(b"\r\n") {
+            if line.len() == V1_MAX_LENGTH {
+                return Err(invalid("version 1 header is too long"));
+            }
+
+            line.push(reader.read_u8().await?);
+        }
+
+        let line = std::str::from_utf8(&line).map_err(|_| invalid("version 1 header is not text"))?;
+        parse_v1(line.trim_end())
+    } else {
+        Err(invalid("missing from a trusted proxy"))
+    }
+}
+
+/// Parse a version 1 header without its CR LF.
+fn parse_v1(line: &str) -> Result<Option<SocketAddr>, TokioIoError> {
+    let fields = line.split(' ').collect::<Vec<_>>();
+
+    match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+
+        ["PROXY", "TCP4", source, _destination, port, _destination_port] | ["PROXY", "TCP6", source, _destination, port, _destination_port] => {
+            let ip = source.parse::<IpAddr>().map_err(|_| invalid("source address is not an IP address"))?;
+            let port = port.parse::<u16>().map_err(|_| invalid("source port is not a port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        },
+
+        _ => Err(invalid("version 1 header is malformed")),
+    }
+}
+
+/// Parse the rest of a version 2 header after its signature.
+fn parse_v2(version_command: u8, family: u8, addresses: &[u8]) -> Result<Option<SocketAddr>, TokioIoError> {
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported version"));
+    }
+
+    // LOCAL connections come from the proxy itself.
+    if version_command & 0xf != V2_COMMAND_PROXY {
+        return Ok(None);
+    }
+
+    match family >> 4 {
+        V2_FAMILY_INET if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        },
+
+        V2_FAMILY_INET6 if addresses.len() >= 36 => {
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&addresses[..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        },
+
+        V2_FAMILY_INET | V2_FAMILY_INET6 => Err(invalid("addresses are too short")),
+
+        // Unix sockets and unspecified families have no client IP address.
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn version_1() {
+        let mut input = &b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 5431\r\nlook\r\n"[..];
+
+        assert_eq!(read_header(&mut input).await.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(input, b"look\r\n");
+
+        assert_eq!(read_header(&mut &b"PROXY UNKNOWN\r\n"[..]).await.unwrap(), None);
+        assert!(read_header(&mut &b"look\r\nlook\r\nlook\r\n"[..]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn version_2() {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11, 0, 12, 192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x15, 0x37]);
+        header.extend_from_slice(b"look\r\n");
+        let mut input = &header[..];
+
+        assert_eq!(read_header(&mut input).await.unwrap(), Some("192.0.2.1:56324".parse().unwrap()));
+        assert_eq!(input, b"look\r\n");
+    }
+}
@@ -0,0 +1,123 @@
+//! TLS for TELNET listeners.
+//!
+//! Certificates and keys are read from PEM files. Keys may be PKCS#8 or
+//! PKCS#1 (RSA).
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio::io::{Error as TokioIoError, ErrorKind};
+use tokio_rustls::rustls::{internal::pemfile, NoClientAuth, ServerConfig};
+
+pub use tokio_rustls::TlsAcceptor;
+
+/// Load a certificate chain and private key into a `TlsAcceptor`.
+pub fn load_acceptor(certificate: &Path, key: &Path) -> Result<TlsAcceptor, TokioIoError> {
+    let invalid = |message: String| TokioIoError::new(ErrorKind::InvalidData, message);
+
+    let certificates = pemfile::certs(&mut BufReader::new(File::open(certificate)?))
+    .map_err(|()| invalid(format!("{} is not a valid PEM certificate file", certificate.display())))?;
+
+    if certificates.is_empty() {
+        return Err(invalid(format!("{} has no certificates", certificate.display())));
+    }
+
+    let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(key)?))
+    .map_err(|()| invalid(format!("{} is not a valid PEM key file", key.display())))?;
+
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut BufReader::new(File::open(key)?))
+        .map_err(|()| invalid(format!("{} is not a valid PEM key file", key.display())))?;
+    }
+
+    let key = keys.into_iter().next()
+    .ok_or_else(|| invalid(format!("{} has no private keys", key.display())))?;
+
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.set_single_cert(certificates, key)
+    .map_err(|err| invalid(format!("invalid certificate or key: {}", err)))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::{SinkExt as _, StreamExt as _};
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::TcpStream;
+    use tokio_rustls::{rustls::{Certificate, ClientConfig}, webpki::DNSNameRef, TlsConnector};
+
+    use crate::{Limiter, Limits, TelnetEvent, TelnetFrame, TelnetListener};
+
+    /// A self-signed certificate for localhost and an acceptor loaded from
+    /// PEM files of it. `name` keeps the files of each test apart.
+    fn self_signed_acceptor(name: &str) -> (rcgen::Certificate, TlsAcceptor) {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let dir = std::env::temp_dir().join(format!("telnet_server_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("cert.pem"), generated.serialize_pem().unwrap()).unwrap();
+        std::fs::write(dir.join("key.pem"), generated.serialize_private_key_pem()).unwrap();
+
+        let acceptor = load_acceptor(&dir.join("cert.pem"), &dir.join("key.pem")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        (generated, acceptor)
+    }
+
+    #[tokio::test]
+    async fn telnet_over_tls() {
+        let (generated, acceptor) = self_signed_acceptor("tls");
+        let mut listener = TelnetListener::bind_tls("127.0.0.1:0", acceptor).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = tokio::spawn(async move {
+            let mut config = ClientConfig::new();
+            config.root_store.add(&Certificate(generated.serialize_der().unwrap())).unwrap();
+            let connector = TlsConnector::from(Arc::new(config));
+            let domain = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+
+            let tcp = TcpStream::connect(addr).await.unwrap();
+            let mut tls = connector.connect(domain, tcp).await.unwrap();
+            tls.write_all(b"look\r\n").await.unwrap();
+
+            let mut received = vec![];
+            tls.read_to_end(&mut received).await.unwrap();
+            received
+        });
+
+        let (accepted, _addr) = listener.accept().await.unwrap();
+        let (mut stream, _) = accepted.handshake().await.unwrap();
+
+        assert_eq!(stream.next().await.unwrap().unwrap(), TelnetEvent::Line("look".into()));
+
+        stream.send(TelnetFrame::Data("Exits: forward".into())).await.unwrap();
+        stream.close().await.unwrap();
+        drop(stream);
+
+        let received = client.await.unwrap();
+        assert!(received.ends_with(b"Exits: forward"));
+    }
+
+    #[tokio::test]
+    async fn stalled_tls_handshakes_time_out_and_release_their_slot() {
+        let (_generated, acceptor) = self_signed_acceptor("tls_timeout");
+        let mut listener = TelnetListener::bind_tls("127.0.0.1:0", acceptor).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let limiter = Limiter::new(Limits { connections_per_ip: 1, ..Limits::default() });
+        listener.set_limiter(limiter.clone());
+        listener.set_handshake_timeout(std::time::Duration::from_millis(100));
+
+        // Connects but never starts TLS.
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (accepted, _addr) = listener.accept().await.unwrap();
+        assert!(limiter.admit(addr.ip()).is_err());
+
+        let err = accepted.handshake().await.err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(limiter.admit(addr.ip()).is_ok());
+    }
+}
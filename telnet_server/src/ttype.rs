@@ -0,0 +1,163 @@
+//! Terminal Type and the Mud Terminal Type Standard.
+//!
+//! https://tools.ietf.org/html/rfc1091
+//! https://tintin.sourceforge.io/protocols/mtts/
+//!
+//! Each IAC SB TTYPE SEND IAC SE asks the client for its next terminal type.
+//! MTTS clients answer with their name, then their terminal type, then
+//! "MTTS <bitvector>". Clients signal the end of their list by repeating
+//! the last type.
+
+use bytes::Bytes;
+
+const IS: u8 = 0;
+const SEND: u8 = 1;
+
+/// The most types to ask for. MTTS only defines three.
+const MAX_TYPES: usize = 3;
+
+/// Capabilities from the MTTS bitvector.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Mtts(pub u32);
+
+impl Mtts {
+    pub const ANSI: u32 = 1;
+    pub const VT100: u32 = 2;
+    pub const UTF8: u32 = 4;
+    pub const COLORS_256: u32 = 8;
+    pub const MOUSE_TRACKING: u32 = 16;
+    pub const OSC_COLOR_PALETTE: u32 = 32;
+    pub const SCREEN_READER: u32 = 64;
+    pub const PROXY: u32 = 128;
+    pub const TRUECOLOR: u32 = 256;
+    pub const MNES: u32 = 512;
+    pub const MSLP: u32 = 1024;
+    pub const SSL: u32 = 2048;
+
+    pub fn has(self, flag: u32) -> bool {
+        self.0 & flag == flag
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        let bits = name.strip_prefix("MTTS ")?;
+        bits.trim().parse().ok().map(Mtts)
+    }
+}
+
+/// What the client reported about itself.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct TerminalType {
+    /// The first type reported. For MTTS clients, this is the client's name.
+    pub client: Option<String>,
+
+    /// The terminal type, such as "XTERM-256COLOR".
+    pub terminal: Option<String>,
+
+    pub mtts: Option<Mtts>,
+}
+
+/// The payload asking for the next terminal type.
+pub(crate) fn send() -> Bytes {
+    Bytes::from_static(&[SEND])
+}
+
+/// Parse IS <type>. Terminal types are case insensitive, so they are
+/// uppercased.
+pub(crate) fn parse(payload: &[u8]) -> Option<String> {
+    match payload.split_first() {
+        Some((&IS, name)) => Some(String::from_utf8_lossy(name).to_uppercase()),
+        _ => None,
+    }
+}
+
+/// What to do after the client sent a terminal type.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// Ask for the next type.
+    AskAgain,
+
+    /// The client has sent all of its types.
+    Done(TerminalType),
+
+    /// The cycle already finished.
+    Ignore,
+}
+
+/// The types received so far while asking for each type in turn.
+#[derive(Debug, Default)]
+pub(crate) struct Cycle {
+    types: Vec<String>,
+    done: bool,
+}
+
+impl Cycle {
+    /// Record a type sent by the client.
+    pub fn receive(&mut self, name: String) -> Step {
+        if self.done {
+            return Step::Ignore;
+        }
+
+        let repeated = self.types.contains(&name);
+        let is_mtts = Mtts::parse(&name).is_some();
+
+        if !repeated {
+            self.types.push(name);
+        }
+
+        if repeated || is_mtts || self.types.len() == MAX_TYPES {
+            self.done = true;
+            Step::Done(self.terminal_type())
+        } else {
+            Step::AskAgain
+        }
+    }
+
+    fn terminal_type(&self) -> TerminalType {
+        let mut types = self.types.iter();
+        let client = types.next().cloned();
+        let terminal = types.next().filter(|name| Mtts::parse(name).is_none()).cloned().or_else(|| client.clone());
+        let mtts = self.types.iter().find_map(|name| Mtts::parse(name));
+
+        TerminalType { client, terminal, mtts }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mtts_client_cycle() {
+        let mut cycle = Cycle::default();
+
+        assert_eq!(cycle.receive("MUDLET".into()), Step::AskAgain);
+        assert_eq!(cycle.receive("XTERM-256COLOR".into()), Step::AskAgain);
+        assert_eq!(cycle.receive("MTTS 271".into()), Step::Done(TerminalType {
+            client: Some("MUDLET".into()),
+            terminal: Some("XTERM-256COLOR".into()),
+            mtts: Some(Mtts(271)),
+        }));
+        assert_eq!(cycle.receive("MTTS 271".into()), Step::Ignore);
+    }
+
+    #[test]
+    fn plain_client_repeats_its_type() {
+        let mut cycle = Cycle::default();
+
+        assert_eq!(parse(b"\x00xterm"), Some("XTERM".into()));
+        assert_eq!(cycle.receive("XTERM".into()), Step::AskAgain);
+        assert_eq!(cycle.receive("XTERM".into()), Step::Done(TerminalType {
+            client: Some("XTERM".into()),
+            terminal: Some("XTERM".into()),
+            mtts: None,
+        }));
+    }
+
+    #[test]
+    fn mtts_flags() {
+        let mtts = Mtts(Mtts::ANSI | Mtts::UTF8 | Mtts::TRUECOLOR);
+
+        assert!(mtts.has(Mtts::UTF8));
+        assert!(!mtts.has(Mtts::SCREEN_READER));
+    }
+}
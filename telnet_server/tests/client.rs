@@ -0,0 +1,88 @@
+//! Driving a small server over a real socket with `TelnetClient`.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures::{SinkExt as _, StreamExt as _};
+use telnet_server::client::Script;
+use telnet_server::{TelnetClient, TelnetEvent, TelnetFrame, TelnetListener, TelnetOption};
+use tokio::io::ErrorKind;
+
+/// Serve one connection: ask for a name, greet it, then echo commands until
+/// `quit`.
+async fn start_server() -> SocketAddr {
+    let mut listener = TelnetListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (accepted, _addr) = listener.accept().await.unwrap();
+        let (mut stream, _addr) = accepted.handshake().await.unwrap();
+
+        stream.send(TelnetFrame::Data("Welcome!\r\n".into())).await.unwrap();
+        stream.send(TelnetFrame::Prompt("Name: ".into())).await.unwrap();
+
+        let mut named = false;
+
+        while let Some(Ok(event)) = stream.next().await {
+            let line = match event {
+                TelnetEvent::Line(line) => line,
+                _ => continue,
+            };
+
+            let reply = match (named, line.as_str()) {
+                (false, name) => {
+                    named = true;
+                    format!("Hello, {}!\r\n", name)
+                },
+                (true, "quit") => {
+                    stream.send(TelnetFrame::Data("Goodbye.\r\n".into())).await.unwrap();
+                    break;
+                },
+                (true, command) => format!("You {}.\r\n", command),
+            };
+
+            stream.send(TelnetFrame::Data(reply.into())).await.unwrap();
+            stream.send(TelnetFrame::Prompt("> ".into())).await.unwrap();
+        }
+
+        let _ = stream.close().await;
+    });
+
+    addr
+}
+
+#[tokio::test]
+async fn scripts_drive_a_session() {
+    let addr = start_server().await;
+    let mut client = TelnetClient::connect(addr).await.unwrap();
+
+    let script = Script::parse("\
+< Welcome!
+< Name:
+> Havvy
+< Hello, Havvy!
+> dance
+< You dance.
+< >
+> quit
+< Goodbye.
+closed
+").unwrap();
+
+    script.run(&mut client).await.unwrap();
+    assert!(client.negotiator().remote_enabled(TelnetOption::EndOfRecord));
+}
+
+#[tokio::test]
+async fn missing_output_times_out() {
+    let addr = start_server().await;
+    let mut client = TelnetClient::connect(addr).await.unwrap();
+    client.set_timeout(Duration::from_millis(200));
+
+    let err = client.expect("Goodbye.").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(err.to_string().contains("Welcome!\\nName: "));
+
+    // What was received is still there for the next expectation.
+    client.expect("Name: ").await.unwrap();
+}